@@ -1,32 +1,110 @@
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use pmx::pipewire::pipewire_client::PipewireClient;
+use pmx::pipewire::ConnectNodesRequest;
+use pmx::pipewire::ConnectPortsRequest;
 use pmx::pipewire::CreateLinkByNameRequest;
 use pmx::pipewire::CreateLinkRequest;
+use pmx::pipewire::GetObjectPropertiesRequest;
 use pmx::pipewire::ListApplicationsRequest;
 use pmx::pipewire::ListDevicesRequest;
 use pmx::pipewire::ListLinksRequest;
 use pmx::pipewire::ListNodesRequest;
 use pmx::pipewire::ListPortsRequest;
+use pmx::pipewire::WatchRegistryRequest;
+use pmx::pipewire::{destroy_link_request, DestroyLinkRequest, GetStatusRequest, LinkPorts};
+use pmx::pipewire::{get_object_request, name_pattern, GetObjectRequest, ListFilter, NamePattern};
+use pmx::pipewire::{DesiredLink, ListDesiredLinksRequest};
+use pmx::pipewire::{ExportGraphRequest, GraphFormat};
+use pmx::pipewire::{
+    ListCoresRequest, ListFactoriesRequest, ListMetadataRequest, ListModulesRequest,
+};
+use pmx::pipewire::{RestoreSnapshotRequest, SaveSnapshotRequest, SnapshotFormat};
 use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use hyper_util::rt::TokioIo;
+use tokio::net::UnixStream;
+use tonic::transport::{Channel, Endpoint, Uri};
 use tonic::Request;
 
 #[derive(Parser)]
 #[command(version, about, long_about=None)]
 struct Arguments {
+    #[arg(
+        short,
+        long,
+        env = "FR_PIPEWIRE_REGISTRY_SERVER",
+        default_value = "http://127.0.0.1:50000"
+    )]
+    server: String,
     #[command(subcommand)]
     command: Option<Commands>,
 }
 
 #[derive(Subcommand)]
 enum Commands {
-    ListNodes {},
+    ListNodes {
+        #[command(flatten)]
+        filter: FilterArguments,
+    },
     ListPorts {
         #[arg(short, long)]
         node_id_filter: Option<u32>,
+        #[command(flatten)]
+        filter: FilterArguments,
+    },
+    ListApplications {
+        #[command(flatten)]
+        filter: FilterArguments,
+    },
+    ListDevices {
+        #[command(flatten)]
+        filter: FilterArguments,
+    },
+    ListLinks {
+        #[arg(short, long)]
+        unhealthy: bool,
+        #[command(flatten)]
+        filter: FilterArguments,
+    },
+    ListModules {
+        #[command(flatten)]
+        filter: FilterArguments,
+    },
+    ListFactories {
+        #[command(flatten)]
+        filter: FilterArguments,
+    },
+    ListMetadata {
+        #[command(flatten)]
+        filter: FilterArguments,
+    },
+    ListCores {
+        #[command(flatten)]
+        filter: FilterArguments,
+    },
+    Properties {
+        #[arg(short, long)]
+        global_id: u32,
+    },
+    GetNode {
+        #[command(flatten)]
+        lookup: LookupArguments,
+    },
+    GetDevice {
+        #[command(flatten)]
+        lookup: LookupArguments,
+    },
+    GetLink {
+        #[command(flatten)]
+        lookup: LookupArguments,
     },
-    ListApplications {},
-    ListDevices {},
-    ListLinks {},
+    GetApplication {
+        #[command(flatten)]
+        lookup: LookupArguments,
+    },
+    Watch {},
+    Status {},
     CreateLink {
         #[arg(short = 'o', long)]
         output_port_id: u32,
@@ -37,6 +115,47 @@ enum Commands {
         #[arg(short = 'm', long)]
         input_node_id: u32,
     },
+    ConnectPorts {
+        output_port: String,
+        input_port: String,
+    },
+    ConnectNodes {
+        output_node: String,
+        input_node: String,
+    },
+    AddDesiredLink {
+        output_port: String,
+        input_port: String,
+    },
+    RemoveDesiredLink {
+        output_port: String,
+        input_port: String,
+    },
+    ListDesiredLinks {},
+    Graph {
+        #[arg(long, value_parser = ["dot", "json"], default_value = "dot")]
+        format: String,
+    },
+    SaveSnapshot {
+        path: PathBuf,
+        #[arg(long, value_parser = ["toml", "json"])]
+        format: Option<String>,
+    },
+    RestoreSnapshot {
+        path: PathBuf,
+        #[arg(long, value_parser = ["toml", "json"])]
+        format: Option<String>,
+        #[arg(long)]
+        remove_others: bool,
+    },
+    Disconnect {
+        #[arg(short = 'l', long, conflicts_with_all = ["output_port_id", "input_port_id"])]
+        link_id: Option<u32>,
+        #[arg(short = 'o', long, requires = "input_port_id")]
+        output_port_id: Option<u32>,
+        #[arg(short = 'i', long, requires = "output_port_id")]
+        input_port_id: Option<u32>,
+    },
     CreateLinkByNodeNames {
         #[arg(short = 'o', long)]
         output_port_id: u32,
@@ -49,6 +168,122 @@ enum Commands {
     },
 }
 
+#[derive(Args)]
+#[group(required = true, multiple = false)]
+struct LookupArguments {
+    #[arg(short, long)]
+    global_id: Option<u32>,
+    #[arg(short = 's', long)]
+    object_serial: Option<u64>,
+    #[arg(short, long)]
+    name: Option<String>,
+}
+
+impl From<LookupArguments> for GetObjectRequest {
+    fn from(arguments: LookupArguments) -> Self {
+        let key = match (arguments.global_id, arguments.object_serial, arguments.name) {
+            (Some(global_id), _, _) => get_object_request::Key::GlobalId(global_id),
+            (_, Some(object_serial), _) => get_object_request::Key::ObjectSerial(object_serial),
+            (_, _, Some(name)) => get_object_request::Key::Name(name),
+            (None, None, None) => return GetObjectRequest { key: None },
+        };
+        GetObjectRequest { key: Some(key) }
+    }
+}
+
+#[derive(Args)]
+struct FilterArguments {
+    #[arg(long)]
+    media_class: Option<String>,
+    #[arg(long, conflicts_with = "regex")]
+    glob: Option<String>,
+    #[arg(long)]
+    regex: Option<String>,
+    #[arg(long, value_parser = ["in", "out"])]
+    direction: Option<String>,
+    #[arg(long)]
+    physical: Option<bool>,
+    #[arg(long)]
+    terminal: Option<bool>,
+    #[arg(long)]
+    monitor: Option<bool>,
+    #[arg(long)]
+    application: Option<String>,
+    #[arg(long)]
+    device_id: Option<u32>,
+    #[arg(long)]
+    dsp_format: Option<String>,
+}
+
+impl From<FilterArguments> for ListFilter {
+    fn from(arguments: FilterArguments) -> Self {
+        let pattern = match (arguments.glob, arguments.regex) {
+            (Some(glob), _) => Some(name_pattern::Pattern::Glob(glob)),
+            (None, Some(regex)) => Some(name_pattern::Pattern::Regex(regex)),
+            (None, None) => None,
+        };
+        let direction = arguments
+            .direction
+            .map(|direction| match direction.as_str() {
+                "in" => pmx::pipewire::port::PortDirection::In as i32,
+                _ => pmx::pipewire::port::PortDirection::Out as i32,
+            });
+        ListFilter {
+            media_class: arguments.media_class,
+            name: pattern.map(|pattern| NamePattern {
+                pattern: Some(pattern),
+            }),
+            direction,
+            physical: arguments.physical,
+            terminal: arguments.terminal,
+            monitor: arguments.monitor,
+            application: arguments.application,
+            device_id: arguments.device_id,
+            dsp_format: arguments.dsp_format,
+        }
+    }
+}
+
+/// Nodes are given by global id or by name on the command line.
+fn node_lookup(node: String) -> GetObjectRequest {
+    let key = match node.parse() {
+        Ok(global_id) => get_object_request::Key::GlobalId(global_id),
+        Err(_) => get_object_request::Key::Name(node),
+    };
+    GetObjectRequest { key: Some(key) }
+}
+
+/// Desired links are given as `node name:port name` for either end.
+fn desired_link(output_port: &str, input_port: &str) -> Result<DesiredLink, Box<dyn Error>> {
+    let (output_node, output_port) = output_port
+        .rsplit_once(':')
+        .ok_or_else(|| format!("{output_port:?} is not of the form node:port"))?;
+    let (input_node, input_port) = input_port
+        .rsplit_once(':')
+        .ok_or_else(|| format!("{input_port:?} is not of the form node:port"))?;
+    Ok(DesiredLink {
+        output_node: output_node.to_owned(),
+        output_port: output_port.to_owned(),
+        input_node: input_node.to_owned(),
+        input_port: input_port.to_owned(),
+    })
+}
+
+/// Snapshot files are read and written here, the server only deals with
+/// their content. Without a format, files ending in `.json` are JSON and
+/// everything else is TOML.
+fn snapshot_format(path: &Path, format: Option<String>) -> SnapshotFormat {
+    let format = format.or_else(|| {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_owned)
+    });
+    match format.as_deref() {
+        Some("json") => SnapshotFormat::Json,
+        _ => SnapshotFormat::Toml,
+    }
+}
+
 pub mod pmx {
     pub mod pipewire {
         tonic::include_proto!("pmx.pipewire");
@@ -72,6 +307,43 @@ pub mod pmx {
         pub mod link {
             tonic::include_proto!("pmx.pipewire.link");
         }
+
+        pub mod module {
+            tonic::include_proto!("pmx.pipewire.module");
+        }
+
+        pub mod factory {
+            tonic::include_proto!("pmx.pipewire.factory");
+        }
+
+        pub mod metadata {
+            tonic::include_proto!("pmx.pipewire.metadata");
+        }
+
+        pub mod core_info {
+            tonic::include_proto!("pmx.pipewire.core_info");
+        }
+    }
+}
+
+async fn connect(server: &str) -> Result<Channel, Box<dyn Error>> {
+    if let Some(path) = server
+        .strip_prefix("unix://")
+        .or_else(|| server.strip_prefix("unix:"))
+    {
+        let path = path.to_owned();
+        let channel =
+            Endpoint::try_from("http://[::]:50000")?
+                .connect_with_connector(tower::service_fn(move |_: Uri| {
+                    let path = path.clone();
+                    async move {
+                        Ok::<_, std::io::Error>(TokioIo::new(UnixStream::connect(path).await?))
+                    }
+                }))
+                .await?;
+        Ok(channel)
+    } else {
+        Ok(Endpoint::from_shared(server.to_owned())?.connect().await?)
     }
 }
 
@@ -79,7 +351,7 @@ pub mod pmx {
 async fn main() -> Result<(), Box<dyn Error>> {
     let cli_arguments = Arguments::parse();
 
-    let mut client = PipewireClient::connect("http://127.0.0.1:50000").await?;
+    let mut client = PipewireClient::new(connect(&cli_arguments.server).await?);
 
     if let Some(command) = cli_arguments.command {
         match command {
@@ -113,28 +385,207 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 let response = client.create_link(request).await;
                 println!("Response={response:#?}");
             }
-            Commands::ListLinks {} => {
-                let request = Request::new(ListLinksRequest {});
+            Commands::ConnectPorts {
+                output_port,
+                input_port,
+            } => {
+                let request = Request::new(ConnectPortsRequest {
+                    output_port,
+                    input_port,
+                });
+                let response = client.connect_ports(request).await?;
+                println!("Response={response:#?}");
+            }
+            Commands::ConnectNodes {
+                output_node,
+                input_node,
+            } => {
+                let request = Request::new(ConnectNodesRequest {
+                    output_node: Some(node_lookup(output_node)),
+                    input_node: Some(node_lookup(input_node)),
+                });
+                let response = client.connect_nodes(request).await?;
+                println!("Response={response:#?}");
+            }
+            Commands::AddDesiredLink {
+                output_port,
+                input_port,
+            } => {
+                let request = Request::new(desired_link(&output_port, &input_port)?);
+                let response = client.add_desired_link(request).await?;
+                println!("Response={response:#?}");
+            }
+            Commands::RemoveDesiredLink {
+                output_port,
+                input_port,
+            } => {
+                let request = Request::new(desired_link(&output_port, &input_port)?);
+                let response = client.remove_desired_link(request).await?;
+                println!("Response={response:#?}");
+            }
+            Commands::SaveSnapshot { path, format } => {
+                let format = snapshot_format(&path, format);
+                let request = Request::new(SaveSnapshotRequest {
+                    format: format as i32,
+                });
+                let response = client.save_snapshot(request).await?.into_inner();
+                std::fs::write(&path, response.content)?;
+                println!("Saved {} links to {}", response.links, path.display());
+            }
+            Commands::RestoreSnapshot {
+                path,
+                format,
+                remove_others,
+            } => {
+                let format = snapshot_format(&path, format);
+                let content = std::fs::read_to_string(&path)?;
+                let request = Request::new(RestoreSnapshotRequest {
+                    content,
+                    format: format as i32,
+                    remove_others,
+                });
+                let response = client.restore_snapshot(request).await?;
+                println!("Response={response:#?}");
+            }
+            Commands::Graph { format } => {
+                let format = match format.as_str() {
+                    "json" => GraphFormat::Json,
+                    _ => GraphFormat::Dot,
+                };
+                let request = Request::new(ExportGraphRequest {
+                    format: format as i32,
+                });
+                let response = client.export_graph(request).await?;
+                println!("{}", response.into_inner().content.trim_end());
+            }
+            Commands::ListDesiredLinks {} => {
+                let request = Request::new(ListDesiredLinksRequest {});
+                let response = client.list_desired_links(request).await?;
+                println!("Response={response:#?}");
+            }
+            Commands::Disconnect {
+                link_id,
+                output_port_id,
+                input_port_id,
+            } => {
+                let target = match (link_id, output_port_id, input_port_id) {
+                    (Some(link_id), _, _) => destroy_link_request::Target::LinkId(link_id),
+                    (None, Some(output_port_id), Some(input_port_id)) => {
+                        destroy_link_request::Target::Ports(LinkPorts {
+                            output_port_id,
+                            input_port_id,
+                        })
+                    }
+                    _ => return Err("Either --link-id or both port ids are required".into()),
+                };
+                let request = Request::new(DestroyLinkRequest {
+                    target: Some(target),
+                });
+                let response = client.destroy_link(request).await?;
+                println!("Response={response:#?}");
+            }
+            Commands::ListLinks { unhealthy, filter } => {
+                let request = Request::new(ListLinksRequest {
+                    unhealthy_only: unhealthy,
+                    filter: Some(filter.into()),
+                });
                 let response = client.list_links(request).await?;
                 println!("Response={response:#?}");
             }
-            Commands::ListNodes {} => {
-                let request = Request::new(ListNodesRequest {});
+            Commands::ListModules { filter } => {
+                let request = Request::new(ListModulesRequest {
+                    filter: Some(filter.into()),
+                });
+                let response = client.list_modules(request).await?;
+                println!("Response={response:#?}");
+            }
+            Commands::ListFactories { filter } => {
+                let request = Request::new(ListFactoriesRequest {
+                    filter: Some(filter.into()),
+                });
+                let response = client.list_factories(request).await?;
+                println!("Response={response:#?}");
+            }
+            Commands::ListMetadata { filter } => {
+                let request = Request::new(ListMetadataRequest {
+                    filter: Some(filter.into()),
+                });
+                let response = client.list_metadata(request).await?;
+                println!("Response={response:#?}");
+            }
+            Commands::ListCores { filter } => {
+                let request = Request::new(ListCoresRequest {
+                    filter: Some(filter.into()),
+                });
+                let response = client.list_cores(request).await?;
+                println!("Response={response:#?}");
+            }
+            Commands::Properties { global_id } => {
+                let request = Request::new(GetObjectPropertiesRequest { global_id });
+                let response = client.get_object_properties(request).await?;
+                println!("Response={response:#?}");
+            }
+            Commands::GetNode { lookup } => {
+                let request = Request::new(GetObjectRequest::from(lookup));
+                let response = client.get_node(request).await?;
+                println!("Response={response:#?}");
+            }
+            Commands::GetDevice { lookup } => {
+                let request = Request::new(GetObjectRequest::from(lookup));
+                let response = client.get_device(request).await?;
+                println!("Response={response:#?}");
+            }
+            Commands::GetLink { lookup } => {
+                let request = Request::new(GetObjectRequest::from(lookup));
+                let response = client.get_link(request).await?;
+                println!("Response={response:#?}");
+            }
+            Commands::GetApplication { lookup } => {
+                let request = Request::new(GetObjectRequest::from(lookup));
+                let response = client.get_application(request).await?;
+                println!("Response={response:#?}");
+            }
+            Commands::Watch {} => {
+                let request = Request::new(WatchRegistryRequest {});
+                let mut events = client.watch_registry(request).await?.into_inner();
+                while let Some(event) = events.message().await? {
+                    println!("Event={event:#?}");
+                }
+            }
+            Commands::Status {} => {
+                let request = Request::new(GetStatusRequest {});
+                let response = client.get_status(request).await?;
+                println!("Response={response:#?}");
+            }
+            Commands::ListNodes { filter } => {
+                let request = Request::new(ListNodesRequest {
+                    filter: Some(filter.into()),
+                });
                 let response = client.list_nodes(request).await?;
                 println!("Response={response:#?}");
             }
-            Commands::ListPorts { node_id_filter } => {
-                let request = Request::new(ListPortsRequest { node_id_filter });
+            Commands::ListPorts {
+                node_id_filter,
+                filter,
+            } => {
+                let request = Request::new(ListPortsRequest {
+                    node_id_filter,
+                    filter: Some(filter.into()),
+                });
                 let response = client.list_ports(request).await?;
                 println!("Response={response:#?}");
             }
-            Commands::ListApplications {} => {
-                let request = Request::new(ListApplicationsRequest {});
+            Commands::ListApplications { filter } => {
+                let request = Request::new(ListApplicationsRequest {
+                    filter: Some(filter.into()),
+                });
                 let response = client.list_applications(request).await?;
                 println!("Response={response:#?}");
             }
-            Commands::ListDevices {} => {
-                let request = Request::new(ListDevicesRequest {});
+            Commands::ListDevices { filter } => {
+                let request = Request::new(ListDevicesRequest {
+                    filter: Some(filter.into()),
+                });
                 let response = client.list_devices(request).await?;
                 println!("Response={response:#?}");
             }
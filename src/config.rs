@@ -0,0 +1,184 @@
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+
+pub const DEFAULT_LISTEN_ADDRESS: &str = "127.0.0.1:50000";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub listen_address: Option<String>,
+    pub pipewire_remote: Option<String>,
+    pub patchbay_file: Option<PathBuf>,
+    pub filters: ObjectFilters,
+    pub startup: StartupConfig,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ObjectFilters {
+    pub ignore_node_names: Vec<String>,
+    pub ignore_media_classes: Vec<String>,
+    pub ignore_application_names: Vec<String>,
+}
+
+impl ObjectFilters {
+    pub fn ignores_node(&self, node_name: &str, media_class: &str) -> bool {
+        self.ignore_node_names.iter().any(|n| n == node_name)
+            || self.ignore_media_classes.iter().any(|m| m == media_class)
+    }
+
+    pub fn ignores_application(&self, application_name: &str) -> bool {
+        self.ignore_application_names
+            .iter()
+            .any(|a| a == application_name)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StartupConfig {
+    pub wait_for_pipewire: bool,
+    pub retry_interval_ms: u64,
+}
+
+impl Default for StartupConfig {
+    fn default() -> Self {
+        StartupConfig {
+            wait_for_pipewire: false,
+            retry_interval_ms: 1000,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ListenAddress {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl FromStr for ListenAddress {
+    type Err = anyhow::Error;
+
+    fn from_str(address: &str) -> Result<Self> {
+        if let Some(path) = address
+            .strip_prefix("unix://")
+            .or_else(|| address.strip_prefix("unix:"))
+        {
+            if path.is_empty() {
+                return Err(anyhow!("Unix socket address {address:?} has no path"));
+            }
+            return Ok(ListenAddress::Unix(PathBuf::from(path)));
+        }
+
+        address
+            .parse()
+            .map(ListenAddress::Tcp)
+            .with_context(|| format!("Invalid listen address {address:?}"))
+    }
+}
+
+pub fn config_directory() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|directory| !directory.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| home::home_dir().map(|home| home.join(".config")))
+        .map(|directory| directory.join("fr-pipewire-registry"))
+}
+
+pub fn config_file_path() -> Option<PathBuf> {
+    config_directory().map(|directory| directory.join("config.toml"))
+}
+
+pub fn patchbay_file_path() -> Option<PathBuf> {
+    config_directory().map(|directory| directory.join("patchbay.toml"))
+}
+
+/// Loads the server configuration. An explicitly given file has to exist,
+/// the default file in the user's config directory is optional.
+pub fn load_config(path: Option<&Path>) -> Result<ServerConfig> {
+    match path {
+        Some(path) => read_config(path),
+        None => match config_file_path() {
+            Some(path) if path.exists() => read_config(&path),
+            _ => Ok(ServerConfig::default()),
+        },
+    }
+}
+
+fn read_config(path: &Path) -> Result<ServerConfig> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file {}", path.display()))?;
+    let config: ServerConfig = toml::from_str(&content)
+        .with_context(|| format!("Invalid config file {}", path.display()))?;
+
+    if let Some(listen_address) = &config.listen_address {
+        listen_address
+            .parse::<ListenAddress>()
+            .with_context(|| format!("Invalid config file {}", path.display()))?;
+    }
+
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(address: &str) -> ListenAddress {
+        address.parse().unwrap()
+    }
+
+    #[test]
+    fn unix_addresses_take_the_path_after_the_scheme() {
+        for (address, expected) in [
+            ("unix:/run/registry.sock", "/run/registry.sock"),
+            ("unix:///run/registry.sock", "/run/registry.sock"),
+            ("unix:registry.sock", "registry.sock"),
+        ] {
+            let ListenAddress::Unix(path) = parse(address) else {
+                panic!("{address} is not a unix address");
+            };
+            assert_eq!(path, Path::new(expected));
+        }
+    }
+
+    #[test]
+    fn unix_addresses_need_a_path() {
+        assert!("unix:".parse::<ListenAddress>().is_err());
+        assert!("unix://".parse::<ListenAddress>().is_err());
+    }
+
+    #[test]
+    fn other_addresses_are_host_and_port() {
+        assert!(matches!(
+            parse("127.0.0.1:50000"),
+            ListenAddress::Tcp(addr) if addr == SocketAddr::from(([127, 0, 0, 1], 50000))
+        ));
+        assert!(matches!(parse("[::1]:50000"), ListenAddress::Tcp(addr) if addr.is_ipv6()));
+        assert!("localhost".parse::<ListenAddress>().is_err());
+        assert!("127.0.0.1".parse::<ListenAddress>().is_err());
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        assert!(
+            toml::from_str::<ServerConfig>("listen_adress = \"unix:/run/registry.sock\"").is_err()
+        );
+        assert!(toml::from_str::<ServerConfig>("[startup]\nwait_for_pipwire = true").is_err());
+        assert!(toml::from_str::<ServerConfig>("[filters]\nignore_node_name = [\"a\"]").is_err());
+    }
+
+    #[test]
+    fn missing_keys_take_their_defaults() {
+        let config: ServerConfig = toml::from_str("[startup]\nwait_for_pipewire = true").unwrap();
+
+        assert!(config.listen_address.is_none());
+        assert!(config.startup.wait_for_pipewire);
+        assert_eq!(config.startup.retry_interval_ms, 1000);
+    }
+}
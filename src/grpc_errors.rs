@@ -0,0 +1,49 @@
+use std::fmt;
+
+use tonic::Status;
+
+#[derive(Debug)]
+pub enum ServiceError {
+    RegistryUnavailable,
+    RegistryNotReady,
+    PipewireUnavailable,
+    NotFound(String),
+    InvalidArgument(String),
+    Timeout(String),
+    Pipewire(anyhow::Error),
+    Storage(anyhow::Error),
+}
+
+impl fmt::Display for ServiceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServiceError::RegistryUnavailable => write!(f, "PipeWire registry is not running"),
+            ServiceError::RegistryNotReady => {
+                write!(f, "PipeWire registry has not finished its initial sync")
+            }
+            ServiceError::PipewireUnavailable => write!(f, "PipeWire is not connected"),
+            ServiceError::NotFound(message) => write!(f, "{message}"),
+            ServiceError::InvalidArgument(message) => write!(f, "{message}"),
+            ServiceError::Timeout(message) => write!(f, "{message}"),
+            ServiceError::Pipewire(error) => write!(f, "PipeWire request failed: {error:#}"),
+            ServiceError::Storage(error) => write!(f, "{error:#}"),
+        }
+    }
+}
+
+impl std::error::Error for ServiceError {}
+
+impl From<ServiceError> for Status {
+    fn from(error: ServiceError) -> Self {
+        let message = error.to_string();
+        match error {
+            ServiceError::RegistryUnavailable
+            | ServiceError::RegistryNotReady
+            | ServiceError::PipewireUnavailable => Status::unavailable(message),
+            ServiceError::NotFound(_) => Status::not_found(message),
+            ServiceError::InvalidArgument(_) => Status::invalid_argument(message),
+            ServiceError::Timeout(_) => Status::deadline_exceeded(message),
+            ServiceError::Pipewire(_) | ServiceError::Storage(_) => Status::internal(message),
+        }
+    }
+}
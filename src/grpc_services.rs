@@ -1,20 +1,42 @@
+use crate::grpc_errors::ServiceError;
+use crate::link_snapshot::{LinkSnapshot, SnapshotFormat};
 use crate::pipewire_factory::PipewireFactoryRequest;
-use crate::pipewire_registry::PipewireRegistryRequests;
+use crate::pipewire_registry::{
+    DesiredLink, DesiredLinkState, DesiredLinkStatus, ListFilter, Lookup, NamePattern,
+    PipewireRegistryRequests, Port, PortDirection, RegistryEntity,
+};
+use crate::port_pairing::pair_ports;
 use pmx::pipewire::pipewire_server::{Pipewire, PipewireServer};
 
 use pmx::pipewire::application::ListApplication;
+use pmx::pipewire::core_info::ListCore;
 use pmx::pipewire::device::ListDevice;
+use pmx::pipewire::factory::ListFactory;
+use pmx::pipewire::metadata::ListMetadata;
+use pmx::pipewire::module::ListModule;
 use pmx::pipewire::node::ListNode;
 use pmx::pipewire::port::ListPort;
 
 use pmx::pipewire::{
-    CreateLinkByNameRequest, CreateLinkReply, CreateLinkRequest, GetPortByObjectSerialRequest,
-    ListApplicationsReply, ListApplicationsRequest, ListDevicesReply, ListDevicesRequest,
-    ListLinksReply, ListLinksRequest, ListNodesReply, ListNodesRequest, ListPortsReply,
-    ListPortsRequest,
+    destroy_link_request, get_object_request, name_pattern, registry_entity, registry_event,
+    ConnectNodesReply, ConnectNodesRequest, ConnectPortsRequest, CreateLinkByNameRequest,
+    CreateLinkReply, CreateLinkRequest, DestroyLinkReply, DestroyLinkRequest, ExportGraphReply,
+    ExportGraphRequest, GetObjectPropertiesReply, GetObjectPropertiesRequest, GetObjectRequest,
+    GetPortByObjectSerialRequest, GetStatusRequest, ListApplicationsReply, ListApplicationsRequest,
+    ListCoresReply, ListCoresRequest, ListDesiredLinksReply, ListDesiredLinksRequest,
+    ListDevicesReply, ListDevicesRequest, ListFactoriesReply, ListFactoriesRequest, ListLinksReply,
+    ListLinksRequest, ListMetadataReply, ListMetadataRequest, ListModulesReply, ListModulesRequest,
+    ListNodesReply, ListNodesRequest, ListPortsReply, ListPortsRequest, RegistryEvent,
+    RegistrySnapshot, RemoveDesiredLinkReply, RestoreSnapshotReply, RestoreSnapshotRequest,
+    SaveSnapshotReply, SaveSnapshotRequest, WatchRegistryRequest,
 };
 
+use std::collections::BTreeSet;
+use std::pin::Pin;
 use std::result::Result;
+use std::time::Duration;
+
+use futures::Stream;
 use tonic::{Request, Response, Status};
 
 pub mod pmx {
@@ -40,9 +62,28 @@ pub mod pmx {
         pub mod link {
             tonic::include_proto!("pmx.pipewire.link");
         }
+
+        pub mod module {
+            tonic::include_proto!("pmx.pipewire.module");
+        }
+
+        pub mod factory {
+            tonic::include_proto!("pmx.pipewire.factory");
+        }
+
+        pub mod metadata {
+            tonic::include_proto!("pmx.pipewire.metadata");
+        }
+
+        pub mod core_info {
+            tonic::include_proto!("pmx.pipewire.core_info");
+        }
     }
 }
 
+const LINK_CREATION_TIMEOUT: Duration = Duration::from_secs(5);
+const REGISTRY_READY_TIMEOUT: Duration = Duration::from_secs(5);
+
 pub struct PipewireService {
     request_sender: tokio::sync::mpsc::UnboundedSender<PipewireRegistryRequests>,
     pipewire_factory_request_sender: pipewire::channel::Sender<PipewireFactoryRequest>,
@@ -60,22 +101,279 @@ impl PipewireService {
     }
 }
 
+impl PipewireService {
+    async fn query_registry<T>(
+        &self,
+        request: impl FnOnce(tokio::sync::oneshot::Sender<T>) -> PipewireRegistryRequests,
+    ) -> Result<T, ServiceError> {
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        let request = request(sender);
+        // The registry drops queries about the graph while PipeWire is not
+        // connected, including the ones it held back when the connection is
+        // lost, so for those a dropped reply means PipeWire is gone.
+        let requires_ready = request.requires_ready();
+        self.request_sender
+            .send(request)
+            .map_err(|_| ServiceError::RegistryUnavailable)?;
+        match tokio::time::timeout(REGISTRY_READY_TIMEOUT, receiver).await {
+            Ok(Ok(reply)) => Ok(reply),
+            Ok(Err(_)) if requires_ready => Err(ServiceError::PipewireUnavailable),
+            Ok(Err(_)) => Err(ServiceError::RegistryUnavailable),
+            Err(_) => Err(ServiceError::RegistryNotReady),
+        }
+    }
+
+    async fn send_factory_request(
+        &self,
+        request: impl FnOnce(tokio::sync::oneshot::Sender<anyhow::Result<()>>) -> PipewireFactoryRequest,
+    ) -> Result<(), ServiceError> {
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        self.pipewire_factory_request_sender
+            .send(request(sender))
+            .map_err(|_| ServiceError::PipewireUnavailable)?;
+        receiver
+            .await
+            .map_err(|_| ServiceError::PipewireUnavailable)?
+            .map_err(ServiceError::Pipewire)
+    }
+
+    async fn resolve_port(
+        &self,
+        name: String,
+        direction: PortDirection,
+    ) -> Result<Port, ServiceError> {
+        let ports = self
+            .query_registry(|reply_sender| PipewireRegistryRequests::ResolvePort {
+                name: name.clone(),
+                direction,
+                reply_sender,
+            })
+            .await?;
+        let kind = match direction {
+            PortDirection::In => "input port",
+            _ => "output port",
+        };
+        single_match(ports, kind, &Lookup::Name(name))
+    }
+
+    async fn restore_link(&self, link: &DesiredLink) -> Result<(), ServiceError> {
+        let output_port = self
+            .resolve_port(
+                format!("{}:{}", link.output_node, link.output_port),
+                PortDirection::Out,
+            )
+            .await?;
+        let input_port = self
+            .resolve_port(
+                format!("{}:{}", link.input_node, link.input_port),
+                PortDirection::In,
+            )
+            .await?;
+        self.create_link_between(output_port, input_port)
+            .await
+            .map(|_| ())
+    }
+
+    async fn resolve_node(
+        &self,
+        request: Option<GetObjectRequest>,
+    ) -> Result<crate::pipewire_registry::Node, ServiceError> {
+        let lookup = Lookup::try_from(request.unwrap_or_default())?;
+        let nodes = self
+            .query_registry(|reply_sender| PipewireRegistryRequests::GetNode {
+                lookup: lookup.clone(),
+                reply_sender,
+            })
+            .await?;
+        single_match(nodes, "node", &lookup)
+    }
+
+    async fn node_ports(
+        &self,
+        node_id: u32,
+        direction: PortDirection,
+    ) -> Result<Vec<Port>, ServiceError> {
+        let filter = ListFilter {
+            node_id: Some(node_id),
+            direction: Some(direction),
+            ..ListFilter::default()
+        };
+        let mut ports = self
+            .query_registry(|reply_sender| PipewireRegistryRequests::ListPorts {
+                filter,
+                reply_sender,
+            })
+            .await?;
+        ports.sort_by_key(|port| port.id);
+        Ok(ports)
+    }
+
+    async fn create_link_between(
+        &self,
+        output_port: Port,
+        input_port: Port,
+    ) -> Result<crate::pipewire_registry::Link, ServiceError> {
+        if output_port.direction != PortDirection::Out {
+            return Err(ServiceError::InvalidArgument(format!(
+                "Port {} is not an output port",
+                output_port.global_id
+            )));
+        }
+        if input_port.direction != PortDirection::In {
+            return Err(ServiceError::InvalidArgument(format!(
+                "Port {} is not an input port",
+                input_port.global_id
+            )));
+        }
+
+        let link_receiver = match self
+            .query_registry(|reply_sender| PipewireRegistryRequests::WaitForLink {
+                output_port_id: output_port.global_id,
+                input_port_id: input_port.global_id,
+                reply_sender,
+            })
+            .await?
+        {
+            Ok(link) => return Ok(link),
+            Err(link_receiver) => link_receiver,
+        };
+
+        self.send_factory_request(|reply_sender| PipewireFactoryRequest::CreateLink {
+            output_port_id: output_port.global_id.to_string(),
+            input_port_id: input_port.global_id.to_string(),
+            output_node_id: output_port.node_id.to_string(),
+            input_node_id: input_port.node_id.to_string(),
+            reply_sender,
+        })
+        .await?;
+
+        tokio::time::timeout(LINK_CREATION_TIMEOUT, link_receiver)
+            .await
+            .map_err(|_| {
+                ServiceError::Timeout(String::from("Timed out waiting for the link to appear"))
+            })?
+            .map_err(|_| ServiceError::RegistryUnavailable)
+    }
+}
+
+/// Ports are given by their global id and have to belong to the given node.
+fn find_port(ports: &[Port], node_id: u32, port_id: u32) -> Option<Port> {
+    ports
+        .iter()
+        .find(|p| p.global_id == port_id && p.node_id == node_id)
+        .cloned()
+}
+
+fn snapshot_format(format: i32) -> Result<SnapshotFormat, ServiceError> {
+    match pmx::pipewire::SnapshotFormat::try_from(format) {
+        Ok(pmx::pipewire::SnapshotFormat::Toml) => Ok(SnapshotFormat::Toml),
+        Ok(pmx::pipewire::SnapshotFormat::Json) => Ok(SnapshotFormat::Json),
+        Err(_) => Err(ServiceError::InvalidArgument(format!(
+            "Unknown snapshot format {format}"
+        ))),
+    }
+}
+
+/// Names are not unique, a lookup matching several objects is rejected
+/// instead of picking one of them.
+fn single_match<T>(matches: Vec<T>, kind: &str, lookup: &Lookup) -> Result<T, ServiceError> {
+    if matches.len() > 1 {
+        let count = matches.len();
+        return Err(ServiceError::InvalidArgument(format!(
+            "{count} {kind}s match {lookup}"
+        )));
+    }
+    matches
+        .into_iter()
+        .next()
+        .ok_or_else(|| ServiceError::NotFound(format!("No {kind} with {lookup} found")))
+}
+
+fn list_filter(filter: Option<pmx::pipewire::ListFilter>) -> Result<ListFilter, ServiceError> {
+    let Some(filter) = filter else {
+        return Ok(ListFilter::default());
+    };
+
+    let name = match filter.name.and_then(|name| name.pattern) {
+        Some(name_pattern::Pattern::Glob(glob)) => {
+            Some(NamePattern::Glob(glob::Pattern::new(&glob).map_err(
+                |error| ServiceError::InvalidArgument(format!("Invalid glob {glob:?}: {error}")),
+            )?))
+        }
+        Some(name_pattern::Pattern::Regex(regex)) => {
+            Some(NamePattern::Regex(regex::Regex::new(&regex).map_err(
+                |error| ServiceError::InvalidArgument(format!("Invalid regex {regex:?}: {error}")),
+            )?))
+        }
+        None => None,
+    };
+    let direction = match filter
+        .direction
+        .map(pmx::pipewire::port::PortDirection::try_from)
+    {
+        Some(Ok(pmx::pipewire::port::PortDirection::In)) => Some(PortDirection::In),
+        Some(Ok(pmx::pipewire::port::PortDirection::Out)) => Some(PortDirection::Out),
+        Some(Ok(pmx::pipewire::port::PortDirection::Unknown)) => Some(PortDirection::Unknown),
+        Some(Err(_)) => {
+            return Err(ServiceError::InvalidArgument(String::from(
+                "Invalid port direction",
+            )))
+        }
+        None => None,
+    };
+
+    Ok(ListFilter {
+        media_class: filter.media_class,
+        name,
+        node_id: None,
+        direction,
+        physical: filter.physical,
+        terminal: filter.terminal,
+        monitor: filter.monitor,
+        application: filter.application,
+        device_id: filter.device_id,
+        dsp_format: filter.dsp_format,
+        unhealthy_only: false,
+    })
+}
+
 #[tonic::async_trait]
 impl Pipewire for PipewireService {
+    type WatchRegistryStream = Pin<Box<dyn Stream<Item = Result<RegistryEvent, Status>> + Send>>;
+
     async fn create_link(
         &self,
         request: Request<CreateLinkRequest>,
     ) -> Result<Response<CreateLinkReply>, Status> {
         let inner = request.into_inner();
-        self.pipewire_factory_request_sender
-            .send(PipewireFactoryRequest::CreateLink {
-                output_port_id: inner.output_port_id.to_string(),
-                input_port_id: inner.input_port_id.to_string(),
-                output_node_id: inner.output_node_id.to_string(),
-                input_node_id: inner.input_node_id.to_string(),
+
+        let ports = self
+            .query_registry(|reply_sender| PipewireRegistryRequests::ListPorts {
+                filter: ListFilter::default(),
+                reply_sender,
             })
-            .unwrap();
-        Ok(Response::new(CreateLinkReply {}))
+            .await?;
+
+        let output_port = find_port(&ports, inner.output_node_id, inner.output_port_id)
+            .ok_or_else(|| {
+                ServiceError::NotFound(format!(
+                    "Node {} has no port {}",
+                    inner.output_node_id, inner.output_port_id
+                ))
+            })?;
+        let input_port =
+            find_port(&ports, inner.input_node_id, inner.input_port_id).ok_or_else(|| {
+                ServiceError::NotFound(format!(
+                    "Node {} has no port {}",
+                    inner.input_node_id, inner.input_port_id
+                ))
+            })?;
+
+        let link = self.create_link_between(output_port, input_port).await?;
+        Ok(Response::new(CreateLinkReply {
+            link_id: link.global_id,
+            object_serial: link.object_serial,
+        }))
     }
 
     async fn create_link_by_name(
@@ -83,41 +381,201 @@ impl Pipewire for PipewireService {
         request: Request<CreateLinkByNameRequest>,
     ) -> Result<Response<CreateLinkReply>, Status> {
         let inner = request.into_inner();
-        self.pipewire_factory_request_sender
-            .send(PipewireFactoryRequest::CreateLinkByNodeName {
-                output_port_id: inner.output_port_id.to_string(),
-                input_port_id: inner.input_port_id.to_string(),
-                output_node_name: inner.output_node_name,
-                input_node_name: inner.input_node_name,
+
+        let nodes = self
+            .query_registry(|reply_sender| PipewireRegistryRequests::GetNodesList {
+                filter: ListFilter::default(),
+                reply_sender,
+            })
+            .await?;
+        let ports = self
+            .query_registry(|reply_sender| PipewireRegistryRequests::ListPorts {
+                filter: ListFilter::default(),
+                reply_sender,
+            })
+            .await?;
+
+        let output_node = nodes
+            .iter()
+            .find(|n| n.node_name == inner.output_node_name)
+            .ok_or_else(|| ServiceError::NotFound(String::from("Output node not found")))?;
+        let input_node = nodes
+            .iter()
+            .find(|n| n.node_name == inner.input_node_name)
+            .ok_or_else(|| ServiceError::NotFound(String::from("Input node not found")))?;
+
+        let output_port = find_port(&ports, output_node.global_id, inner.output_port_id)
+            .ok_or_else(|| {
+                ServiceError::NotFound(format!(
+                    "Node {} has no port {}",
+                    output_node.global_id, inner.output_port_id
+                ))
+            })?;
+        let input_port =
+            find_port(&ports, input_node.global_id, inner.input_port_id).ok_or_else(|| {
+                ServiceError::NotFound(format!(
+                    "Node {} has no port {}",
+                    input_node.global_id, inner.input_port_id
+                ))
+            })?;
+
+        let link = self.create_link_between(output_port, input_port).await?;
+        Ok(Response::new(CreateLinkReply {
+            link_id: link.global_id,
+            object_serial: link.object_serial,
+        }))
+    }
+
+    async fn connect_ports(
+        &self,
+        request: Request<ConnectPortsRequest>,
+    ) -> Result<Response<CreateLinkReply>, Status> {
+        let inner = request.into_inner();
+        let output_port = self
+            .resolve_port(inner.output_port, PortDirection::Out)
+            .await?;
+        let input_port = self
+            .resolve_port(inner.input_port, PortDirection::In)
+            .await?;
+
+        let link = self.create_link_between(output_port, input_port).await?;
+        Ok(Response::new(CreateLinkReply {
+            link_id: link.global_id,
+            object_serial: link.object_serial,
+        }))
+    }
+
+    async fn connect_nodes(
+        &self,
+        request: Request<ConnectNodesRequest>,
+    ) -> Result<Response<ConnectNodesReply>, Status> {
+        let inner = request.into_inner();
+        let output_node = self.resolve_node(inner.output_node).await?;
+        let input_node = self.resolve_node(inner.input_node).await?;
+
+        let output_ports = self
+            .node_ports(output_node.global_id, PortDirection::Out)
+            .await?;
+        let input_ports = self
+            .node_ports(input_node.global_id, PortDirection::In)
+            .await?;
+        let pairs = pair_ports(output_ports, input_ports);
+        if pairs.is_empty() {
+            return Err(ServiceError::InvalidArgument(format!(
+                "No matching ports between nodes {} and {}",
+                output_node.node_name, input_node.node_name
+            ))
+            .into());
+        }
+
+        // Links that already existed are left alone when a later pair fails,
+        // the ones created here are removed again.
+        let existing: Vec<u32> = self
+            .query_registry(|reply_sender| PipewireRegistryRequests::ListLinks {
+                filter: ListFilter {
+                    node_id: Some(output_node.global_id),
+                    ..ListFilter::default()
+                },
+                reply_sender,
             })
-            .unwrap();
-        Ok(Response::new(CreateLinkReply {}))
+            .await?
+            .into_iter()
+            .map(|link| link.global_id)
+            .collect();
+
+        let mut links = Vec::new();
+        for (output_port, input_port) in pairs {
+            match self.create_link_between(output_port, input_port).await {
+                Ok(link) => links.push(link),
+                Err(error) => {
+                    let mut removed = 0;
+                    for link in links.iter().filter(|l| !existing.contains(&l.global_id)) {
+                        let result = self
+                            .send_factory_request(|reply_sender| {
+                                PipewireFactoryRequest::DestroyLink {
+                                    link_id: link.global_id,
+                                    reply_sender,
+                                }
+                            })
+                            .await;
+                        if result.is_ok() {
+                            removed += 1;
+                        }
+                    }
+                    let status = Status::from(error);
+                    return Err(Status::new(
+                        status.code(),
+                        format!(
+                            "{}, removed the {removed} links created before",
+                            status.message()
+                        ),
+                    ));
+                }
+            }
+        }
+        Ok(Response::new(ConnectNodesReply {
+            links: links
+                .into_iter()
+                .map(|link| CreateLinkReply {
+                    link_id: link.global_id,
+                    object_serial: link.object_serial,
+                })
+                .collect(),
+        }))
+    }
+
+    async fn destroy_link(
+        &self,
+        request: Request<DestroyLinkRequest>,
+    ) -> Result<Response<DestroyLinkReply>, Status> {
+        let target = request.into_inner().target.ok_or_else(|| {
+            ServiceError::InvalidArgument(String::from(
+                "Either a link id or a port pair is required",
+            ))
+        })?;
+
+        let link = self
+            .query_registry(|reply_sender| PipewireRegistryRequests::ListLinks {
+                filter: ListFilter::default(),
+                reply_sender,
+            })
+            .await?
+            .into_iter()
+            .find(|l| match &target {
+                destroy_link_request::Target::LinkId(link_id) => l.global_id == *link_id,
+                destroy_link_request::Target::Ports(ports) => {
+                    l.output_port_id == ports.output_port_id
+                        && l.input_port_id == ports.input_port_id
+                }
+            })
+            .ok_or_else(|| ServiceError::NotFound(String::from("Link not found")))?;
+
+        self.send_factory_request(|reply_sender| PipewireFactoryRequest::DestroyLink {
+            link_id: link.global_id,
+            reply_sender,
+        })
+        .await?;
+
+        Ok(Response::new(DestroyLinkReply {}))
     }
 
     async fn list_applications(
         &self,
-        _request: Request<ListApplicationsRequest>,
+        request: Request<ListApplicationsRequest>,
     ) -> Result<Response<ListApplicationsReply>, Status> {
-        let (sender, receiver) = tokio::sync::oneshot::channel();
-        let service_request = PipewireRegistryRequests::GetApplicationsList {
-            reply_sender: sender,
-        };
-        self.request_sender.send(service_request).unwrap();
-        let service_reply = receiver.await.unwrap();
+        let filter = list_filter(request.into_inner().filter)?;
+        let service_reply = self
+            .query_registry(
+                |reply_sender| PipewireRegistryRequests::GetApplicationsList {
+                    filter,
+                    reply_sender,
+                },
+            )
+            .await?;
         let reply = ListApplicationsReply {
             applications: service_reply
                 .into_iter()
-                .map(|a| ListApplication {
-                    object_serial: a.object_serial as u32,
-                    module_id: a.module_id as u32,
-                    pipewire_protocol: a.pipewire_protocol,
-                    pipewire_sec_pid: a.pipewire_sec_pid,
-                    pipewire_sec_uid: a.pipewire_sec_uid,
-                    pipewire_sec_gid: a.pipewire_sec_gid,
-                    pipewire_sec_socket: a.pipewire_sec_socket,
-                    pipewire_access: a.pipewire_access,
-                    name: a.name,
-                })
+                .map(ListApplication::from)
                 .collect(),
         };
 
@@ -126,55 +584,114 @@ impl Pipewire for PipewireService {
 
     async fn list_links(
         &self,
-        _request: Request<ListLinksRequest>,
+        request: Request<ListLinksRequest>,
     ) -> Result<Response<ListLinksReply>, Status> {
-        let (sender, receiver) = tokio::sync::oneshot::channel();
-        let service_request = PipewireRegistryRequests::ListLinks {
-            reply_sender: sender,
+        let request = request.into_inner();
+        let filter = ListFilter {
+            unhealthy_only: request.unhealthy_only,
+            ..list_filter(request.filter)?
         };
-        self.request_sender.send(service_request).unwrap();
-        let service_reply = receiver.await.unwrap();
+        let service_reply = self
+            .query_registry(|reply_sender| PipewireRegistryRequests::ListLinks {
+                filter,
+                reply_sender,
+            })
+            .await?;
         let reply = ListLinksReply {
             links: service_reply
                 .into_iter()
-                .map(|l| pmx::pipewire::link::Link {
-                    object_serial: l.object_serial as u32,
-                    factory_id: l.factory_id as u32,
-                    client_id: l.client_id as u32,
-                    output_port_id: l.output_port_id as u32,
-                    input_port_id: l.input_port_id as u32,
-                    output_node_id: l.output_node_id as u32,
-                    input_node_id: l.input_node_id as u32,
-                })
+                .map(pmx::pipewire::link::Link::from)
                 .collect(),
         };
 
         Ok(Response::new(reply))
     }
 
+    async fn list_modules(
+        &self,
+        request: Request<ListModulesRequest>,
+    ) -> Result<Response<ListModulesReply>, Status> {
+        let filter = list_filter(request.into_inner().filter)?;
+        let service_reply = self
+            .query_registry(|reply_sender| PipewireRegistryRequests::ListModules {
+                filter,
+                reply_sender,
+            })
+            .await?;
+        let reply = ListModulesReply {
+            modules: service_reply.into_iter().map(ListModule::from).collect(),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    async fn list_factories(
+        &self,
+        request: Request<ListFactoriesRequest>,
+    ) -> Result<Response<ListFactoriesReply>, Status> {
+        let filter = list_filter(request.into_inner().filter)?;
+        let service_reply = self
+            .query_registry(|reply_sender| PipewireRegistryRequests::ListFactories {
+                filter,
+                reply_sender,
+            })
+            .await?;
+        let reply = ListFactoriesReply {
+            factories: service_reply.into_iter().map(ListFactory::from).collect(),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    async fn list_metadata(
+        &self,
+        request: Request<ListMetadataRequest>,
+    ) -> Result<Response<ListMetadataReply>, Status> {
+        let filter = list_filter(request.into_inner().filter)?;
+        let service_reply = self
+            .query_registry(|reply_sender| PipewireRegistryRequests::ListMetadata {
+                filter,
+                reply_sender,
+            })
+            .await?;
+        let reply = ListMetadataReply {
+            metadata: service_reply.into_iter().map(ListMetadata::from).collect(),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    async fn list_cores(
+        &self,
+        request: Request<ListCoresRequest>,
+    ) -> Result<Response<ListCoresReply>, Status> {
+        let filter = list_filter(request.into_inner().filter)?;
+        let service_reply = self
+            .query_registry(|reply_sender| PipewireRegistryRequests::ListCores {
+                filter,
+                reply_sender,
+            })
+            .await?;
+        let reply = ListCoresReply {
+            cores: service_reply.into_iter().map(ListCore::from).collect(),
+        };
+
+        Ok(Response::new(reply))
+    }
+
     async fn list_nodes(
         &self,
-        _request: tonic::Request<ListNodesRequest>,
+        request: tonic::Request<ListNodesRequest>,
     ) -> Result<Response<ListNodesReply>, Status> {
-        let (sender, receiver) = tokio::sync::oneshot::channel();
-        let service_request = PipewireRegistryRequests::GetNodesList {
-            reply_sender: sender,
-        };
-        self.request_sender.send(service_request).unwrap();
-        let service_reply = receiver.await.unwrap();
+        let filter = list_filter(request.into_inner().filter)?;
+        let service_reply = self
+            .query_registry(|reply_sender| PipewireRegistryRequests::GetNodesList {
+                filter,
+                reply_sender,
+            })
+            .await?;
         let reply = ListNodesReply {
-            nodes: service_reply
-                .into_iter()
-                .map(|n| ListNode {
-                    object_serial: n.object_serial as u32,
-                    factory_id: n.factory_id as u32,
-                    client_id: n.client_id as u32,
-                    client_api: n.client_api,
-                    application_name: n.application_name,
-                    name: n.node_name,
-                    media_class: n.media_class,
-                })
-                .collect(),
+            nodes: service_reply.into_iter().map(ListNode::from).collect(),
         };
 
         Ok(Response::new(reply))
@@ -182,27 +699,17 @@ impl Pipewire for PipewireService {
 
     async fn list_devices(
         &self,
-        _request: Request<ListDevicesRequest>,
+        request: Request<ListDevicesRequest>,
     ) -> Result<Response<ListDevicesReply>, Status> {
-        let (sender, receiver) = tokio::sync::oneshot::channel();
-        let service_request = PipewireRegistryRequests::GetDevicesList {
-            reply_sender: sender,
-        };
-        self.request_sender.send(service_request).unwrap();
-        let service_reply = receiver.await.unwrap();
+        let filter = list_filter(request.into_inner().filter)?;
+        let service_reply = self
+            .query_registry(|reply_sender| PipewireRegistryRequests::GetDevicesList {
+                filter,
+                reply_sender,
+            })
+            .await?;
         let reply = ListDevicesReply {
-            devices: service_reply
-                .into_iter()
-                .map(|d| ListDevice {
-                    factory_id: d.factory_id as u32,
-                    object_serial: d.object_serial as u32,
-                    client_id: d.client_id as u32,
-                    name: d.name,
-                    description: d.description,
-                    nick: d.nick,
-                    media_class: d.media_class,
-                })
-                .collect(),
+            devices: service_reply.into_iter().map(ListDevice::from).collect(),
         };
 
         Ok(Response::new(reply))
@@ -212,115 +719,700 @@ impl Pipewire for PipewireService {
         &self,
         request: Request<ListPortsRequest>,
     ) -> Result<Response<ListPortsReply>, Status> {
-        let (sender, receiver) = tokio::sync::oneshot::channel();
-        self.request_sender
-            .send(PipewireRegistryRequests::ListPorts {
-                reply_sender: sender,
+        let request = request.into_inner();
+        let filter = ListFilter {
+            node_id: request.node_id_filter,
+            ..list_filter(request.filter)?
+        };
+        let service_reply = self
+            .query_registry(|reply_sender| PipewireRegistryRequests::ListPorts {
+                filter,
+                reply_sender,
             })
-            .unwrap();
-
-        let service_reply = receiver.await.unwrap().into_iter();
-
-        if let Some(node_id_filter) = request.into_inner().node_id_filter {
-            let reply = ListPortsReply {
-                ports: service_reply
-                    .filter(|p| p.node_id == node_id_filter as u16)
-                    .map(|p| ListPort {
-                        id: p.id as u32,
-                        node_id: p.node_id as u32,
-                        name: p.name,
-                        direction: match p.direction {
-                            crate::pipewire_registry::PortDirection::In => {
-                                pmx::pipewire::port::PortDirection::In as i32
-                            }
-                            crate::pipewire_registry::PortDirection::Out => {
-                                pmx::pipewire::port::PortDirection::Out as i32
-                            }
-                            crate::pipewire_registry::PortDirection::Unknown => {
-                                pmx::pipewire::port::PortDirection::Unknown as i32
-                            }
-                        },
-                        physical: p.physical,
-                        alias: p.alias,
-                        group: p.group,
-                        path: p.path,
-                        dsp_format: p.dsp_format,
-                        audio_channel: p.audio_channel,
-                        object_serial: p.object_serial as u32,
-                    })
-                    .collect(),
-            };
-            Ok(Response::new(reply))
-        } else {
-            let reply = ListPortsReply {
-                ports: service_reply
-                    .map(|p| ListPort {
-                        id: p.id as u32,
-                        node_id: p.node_id as u32,
-                        name: p.name,
-                        direction: match p.direction {
-                            crate::pipewire_registry::PortDirection::In => {
-                                pmx::pipewire::port::PortDirection::In as i32
-                            }
-                            crate::pipewire_registry::PortDirection::Out => {
-                                pmx::pipewire::port::PortDirection::Out as i32
-                            }
-                            crate::pipewire_registry::PortDirection::Unknown => {
-                                pmx::pipewire::port::PortDirection::Unknown as i32
-                            }
-                        },
-                        physical: p.physical,
-                        alias: p.alias,
-                        group: p.group,
-                        path: p.path,
-                        dsp_format: p.dsp_format,
-                        audio_channel: p.audio_channel,
-                        object_serial: p.object_serial as u32,
-                    })
-                    .collect(),
-            };
-            Ok(Response::new(reply))
+            .await?;
+
+        let reply = ListPortsReply {
+            ports: service_reply.into_iter().map(ListPort::from).collect(),
+        };
+        Ok(Response::new(reply))
+    }
+
+    async fn get_object_properties(
+        &self,
+        request: Request<GetObjectPropertiesRequest>,
+    ) -> Result<Response<GetObjectPropertiesReply>, Status> {
+        let global_id = request.into_inner().global_id;
+        let properties = self
+            .query_registry(
+                |reply_sender| PipewireRegistryRequests::GetObjectProperties {
+                    global_id,
+                    reply_sender,
+                },
+            )
+            .await?
+            .ok_or_else(|| ServiceError::NotFound(format!("Object {global_id} not found")))?;
+        Ok(Response::new(GetObjectPropertiesReply {
+            properties: properties.into_iter().collect(),
+        }))
+    }
+
+    async fn get_node(
+        &self,
+        request: Request<GetObjectRequest>,
+    ) -> Result<Response<ListNode>, Status> {
+        let lookup = Lookup::try_from(request.into_inner())?;
+        let nodes = self
+            .query_registry(|reply_sender| PipewireRegistryRequests::GetNode {
+                lookup: lookup.clone(),
+                reply_sender,
+            })
+            .await?;
+        let node = single_match(nodes, "node", &lookup)?;
+        Ok(Response::new(ListNode::from(node)))
+    }
+
+    async fn get_device(
+        &self,
+        request: Request<GetObjectRequest>,
+    ) -> Result<Response<ListDevice>, Status> {
+        let lookup = Lookup::try_from(request.into_inner())?;
+        let devices = self
+            .query_registry(|reply_sender| PipewireRegistryRequests::GetDevice {
+                lookup: lookup.clone(),
+                reply_sender,
+            })
+            .await?;
+        let device = single_match(devices, "device", &lookup)?;
+        Ok(Response::new(ListDevice::from(device)))
+    }
+
+    async fn get_link(
+        &self,
+        request: Request<GetObjectRequest>,
+    ) -> Result<Response<pmx::pipewire::link::Link>, Status> {
+        let lookup = Lookup::try_from(request.into_inner())?;
+        if let Lookup::Name(_) = lookup {
+            return Err(ServiceError::InvalidArgument(String::from("Links have no name")).into());
         }
+        let links = self
+            .query_registry(|reply_sender| PipewireRegistryRequests::GetLink {
+                lookup: lookup.clone(),
+                reply_sender,
+            })
+            .await?;
+        let link = single_match(links, "link", &lookup)?;
+        Ok(Response::new(pmx::pipewire::link::Link::from(link)))
+    }
+
+    async fn get_application(
+        &self,
+        request: Request<GetObjectRequest>,
+    ) -> Result<Response<ListApplication>, Status> {
+        let lookup = Lookup::try_from(request.into_inner())?;
+        let applications = self
+            .query_registry(|reply_sender| PipewireRegistryRequests::GetApplication {
+                lookup: lookup.clone(),
+                reply_sender,
+            })
+            .await?;
+        let application = single_match(applications, "application", &lookup)?;
+        Ok(Response::new(ListApplication::from(application)))
     }
 
     async fn get_port_by_object_serial(
         &self,
         request: Request<GetPortByObjectSerialRequest>,
     ) -> Result<tonic::Response<ListPort>, Status> {
-        let (sender, receiver) = tokio::sync::oneshot::channel();
-        self.request_sender
-            .send(PipewireRegistryRequests::GetPortByObjectSerial {
-                object_serial: request.into_inner().object_serial as u16,
-                reply_sender: sender,
-            })
-            .unwrap();
-        let response = receiver.await;
-        if let Some(port) = response.unwrap() {
-            Ok(Response::new(ListPort {
-                id: port.id as u32,
-                node_id: port.node_id as u32,
-                name: port.name,
-                direction: match port.direction {
-                    crate::pipewire_registry::PortDirection::In => {
-                        pmx::pipewire::port::PortDirection::In as i32
-                    }
-                    crate::pipewire_registry::PortDirection::Out => {
-                        pmx::pipewire::port::PortDirection::Out as i32
-                    }
-                    crate::pipewire_registry::PortDirection::Unknown => {
-                        pmx::pipewire::port::PortDirection::Unknown as i32
-                    }
+        let object_serial = request.into_inner().object_serial;
+        let port = self
+            .query_registry(
+                |reply_sender| PipewireRegistryRequests::GetPortByObjectSerial {
+                    object_serial,
+                    reply_sender,
                 },
-                physical: port.physical,
-                alias: port.alias,
-                group: port.group,
-                path: port.path,
-                dsp_format: port.dsp_format,
-                audio_channel: port.audio_channel,
-                object_serial: port.object_serial as u32,
-            }))
-        } else {
-            Err(Status::not_found("Port not found"))
+            )
+            .await?
+            .ok_or_else(|| ServiceError::NotFound(String::from("Port not found")))?;
+        Ok(Response::new(ListPort::from(port)))
+    }
+
+    async fn get_status(
+        &self,
+        _request: Request<GetStatusRequest>,
+    ) -> Result<Response<pmx::pipewire::RegistryStatus>, Status> {
+        let status = self
+            .query_registry(|reply_sender| PipewireRegistryRequests::GetStatus { reply_sender })
+            .await?;
+        Ok(Response::new(status.into()))
+    }
+
+    async fn watch_registry(
+        &self,
+        _request: Request<WatchRegistryRequest>,
+    ) -> Result<Response<Self::WatchRegistryStream>, Status> {
+        let events = self
+            .query_registry(|reply_sender| PipewireRegistryRequests::WatchRegistry { reply_sender })
+            .await?;
+
+        // The registry drops watchers that fall behind, so their stream ends
+        // with an error telling them to watch again.
+        let stream = futures::stream::unfold(Some(events), |events| async move {
+            let mut events = events?;
+            match events.recv().await {
+                Some(event) => Some((Ok(RegistryEvent::from(event)), Some(events))),
+                None => Some((
+                    Err(Status::aborted(
+                        "Fell behind the registry events, watch the registry again",
+                    )),
+                    None,
+                )),
+            }
+        });
+
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn add_desired_link(
+        &self,
+        request: Request<pmx::pipewire::DesiredLink>,
+    ) -> Result<Response<pmx::pipewire::DesiredLinkStatus>, Status> {
+        let link = DesiredLink::try_from(request.into_inner())?;
+        let status = self
+            .query_registry(|reply_sender| PipewireRegistryRequests::AddDesiredLink {
+                link,
+                reply_sender,
+            })
+            .await?
+            .map_err(ServiceError::Storage)?;
+        Ok(Response::new(status.into()))
+    }
+
+    async fn remove_desired_link(
+        &self,
+        request: Request<pmx::pipewire::DesiredLink>,
+    ) -> Result<Response<RemoveDesiredLinkReply>, Status> {
+        let link = DesiredLink::try_from(request.into_inner())?;
+        let description = link.to_string();
+        let removed = self
+            .query_registry(|reply_sender| PipewireRegistryRequests::RemoveDesiredLink {
+                link,
+                reply_sender,
+            })
+            .await?
+            .map_err(ServiceError::Storage)?;
+        if !removed {
+            return Err(
+                ServiceError::NotFound(format!("Desired link {description} not found")).into(),
+            );
+        }
+        Ok(Response::new(RemoveDesiredLinkReply {}))
+    }
+
+    async fn save_snapshot(
+        &self,
+        request: Request<SaveSnapshotRequest>,
+    ) -> Result<Response<SaveSnapshotReply>, Status> {
+        let format = snapshot_format(request.into_inner().format)?;
+        let links = self
+            .query_registry(|reply_sender| PipewireRegistryRequests::ListLinkNames { reply_sender })
+            .await?;
+
+        let links: BTreeSet<DesiredLink> = links.into_iter().map(|(_, names)| names).collect();
+        let snapshot = LinkSnapshot {
+            links: links.into_iter().collect(),
+        };
+        let content = snapshot.serialize(format).map_err(ServiceError::Storage)?;
+        Ok(Response::new(SaveSnapshotReply {
+            content,
+            links: snapshot.links.len() as u32,
+        }))
+    }
+
+    async fn restore_snapshot(
+        &self,
+        request: Request<RestoreSnapshotRequest>,
+    ) -> Result<Response<RestoreSnapshotReply>, Status> {
+        let inner = request.into_inner();
+        let format = snapshot_format(inner.format)?;
+        let snapshot = LinkSnapshot::parse(&inner.content, format)
+            .map_err(|error| ServiceError::InvalidArgument(format!("{error:#}")))?;
+        let wanted: BTreeSet<DesiredLink> = snapshot.links.into_iter().collect();
+
+        let current = self
+            .query_registry(|reply_sender| PipewireRegistryRequests::ListLinkNames { reply_sender })
+            .await?;
+        let existing: BTreeSet<&DesiredLink> = current.iter().map(|(_, names)| names).collect();
+
+        let mut reply = RestoreSnapshotReply::default();
+        for link in wanted.iter().filter(|link| !existing.contains(link)) {
+            match self.restore_link(link).await {
+                Ok(()) => reply.created += 1,
+                Err(error) => reply.failures.push(format!("{link}: {error}")),
+            }
+        }
+
+        if inner.remove_others {
+            for (link, names) in current.iter().filter(|(_, names)| !wanted.contains(names)) {
+                let result = self
+                    .send_factory_request(|reply_sender| PipewireFactoryRequest::DestroyLink {
+                        link_id: link.global_id,
+                        reply_sender,
+                    })
+                    .await;
+                match result {
+                    Ok(()) => reply.removed += 1,
+                    Err(error) => reply.failures.push(format!("{names}: {error}")),
+                }
+            }
+        }
+
+        Ok(Response::new(reply))
+    }
+
+    async fn export_graph(
+        &self,
+        request: Request<ExportGraphRequest>,
+    ) -> Result<Response<ExportGraphReply>, Status> {
+        let format = pmx::pipewire::GraphFormat::try_from(request.into_inner().format)
+            .map_err(|error| ServiceError::InvalidArgument(error.to_string()))?;
+        let graph = self
+            .query_registry(|reply_sender| PipewireRegistryRequests::GetGraph { reply_sender })
+            .await?;
+        let content = match format {
+            pmx::pipewire::GraphFormat::Dot => graph.to_dot(),
+            pmx::pipewire::GraphFormat::Json => graph.to_json(),
+        };
+        Ok(Response::new(ExportGraphReply { content }))
+    }
+
+    async fn list_desired_links(
+        &self,
+        _request: Request<ListDesiredLinksRequest>,
+    ) -> Result<Response<ListDesiredLinksReply>, Status> {
+        let links = self
+            .query_registry(|reply_sender| PipewireRegistryRequests::ListDesiredLinks {
+                reply_sender,
+            })
+            .await?;
+        Ok(Response::new(ListDesiredLinksReply {
+            links: links
+                .into_iter()
+                .map(pmx::pipewire::DesiredLinkStatus::from)
+                .collect(),
+        }))
+    }
+}
+
+impl TryFrom<GetObjectRequest> for Lookup {
+    type Error = ServiceError;
+
+    fn try_from(request: GetObjectRequest) -> Result<Self, Self::Error> {
+        match request.key {
+            Some(get_object_request::Key::GlobalId(global_id)) => Ok(Lookup::GlobalId(global_id)),
+            Some(get_object_request::Key::ObjectSerial(object_serial)) => {
+                Ok(Lookup::ObjectSerial(object_serial))
+            }
+            Some(get_object_request::Key::Name(name)) => Ok(Lookup::Name(name)),
+            None => Err(ServiceError::InvalidArgument(String::from(
+                "A global id, object serial or name is required",
+            ))),
+        }
+    }
+}
+
+impl TryFrom<pmx::pipewire::DesiredLink> for DesiredLink {
+    type Error = ServiceError;
+
+    fn try_from(link: pmx::pipewire::DesiredLink) -> Result<Self, Self::Error> {
+        if [
+            &link.output_node,
+            &link.output_port,
+            &link.input_node,
+            &link.input_port,
+        ]
+        .iter()
+        .any(|name| name.is_empty())
+        {
+            return Err(ServiceError::InvalidArgument(String::from(
+                "Desired links need both node and port names on each end",
+            )));
+        }
+        Ok(DesiredLink {
+            output_node: link.output_node,
+            output_port: link.output_port,
+            input_node: link.input_node,
+            input_port: link.input_port,
+        })
+    }
+}
+
+impl From<DesiredLink> for pmx::pipewire::DesiredLink {
+    fn from(link: DesiredLink) -> Self {
+        pmx::pipewire::DesiredLink {
+            output_node: link.output_node,
+            output_port: link.output_port,
+            input_node: link.input_node,
+            input_port: link.input_port,
         }
     }
 }
+
+impl From<DesiredLinkStatus> for pmx::pipewire::DesiredLinkStatus {
+    fn from(status: DesiredLinkStatus) -> Self {
+        let (state, link_id, reason) = match status.state {
+            DesiredLinkState::Pending => (pmx::pipewire::DesiredLinkState::Pending, None, None),
+            DesiredLinkState::Satisfied { link_id } => (
+                pmx::pipewire::DesiredLinkState::Satisfied,
+                Some(link_id),
+                None,
+            ),
+            DesiredLinkState::Broken { reason } => {
+                (pmx::pipewire::DesiredLinkState::Broken, None, Some(reason))
+            }
+        };
+        pmx::pipewire::DesiredLinkStatus {
+            link: Some(status.link.into()),
+            state: state as i32,
+            link_id,
+            reason,
+        }
+    }
+}
+
+impl From<crate::pipewire_registry::Application> for ListApplication {
+    fn from(a: crate::pipewire_registry::Application) -> Self {
+        ListApplication {
+            object_serial: a.object_serial,
+            module_id: a.module_id,
+            pipewire_protocol: a.pipewire_protocol,
+            pipewire_sec_pid: a.pipewire_sec_pid,
+            pipewire_sec_uid: a.pipewire_sec_uid,
+            pipewire_sec_gid: a.pipewire_sec_gid,
+            pipewire_sec_socket: a.pipewire_sec_socket,
+            pipewire_access: a.pipewire_access,
+            name: a.name,
+            global_id: a.global_id,
+        }
+    }
+}
+
+impl From<crate::pipewire_registry::Link> for pmx::pipewire::link::Link {
+    fn from(l: crate::pipewire_registry::Link) -> Self {
+        pmx::pipewire::link::Link {
+            object_serial: l.object_serial,
+            factory_id: l.factory_id,
+            client_id: l.client_id,
+            output_port_id: l.output_port_id,
+            input_port_id: l.input_port_id,
+            output_node_id: l.output_node_id,
+            input_node_id: l.input_node_id,
+            global_id: l.global_id,
+            state: pmx::pipewire::link::LinkState::from(&l.state) as i32,
+            error: match l.state {
+                crate::pipewire_registry::LinkState::Error(error) => Some(error),
+                _ => None,
+            },
+            format: l.format,
+        }
+    }
+}
+
+impl From<&crate::pipewire_registry::LinkState> for pmx::pipewire::link::LinkState {
+    fn from(state: &crate::pipewire_registry::LinkState) -> Self {
+        match state {
+            crate::pipewire_registry::LinkState::Unknown => pmx::pipewire::link::LinkState::Unknown,
+            crate::pipewire_registry::LinkState::Error(_) => pmx::pipewire::link::LinkState::Error,
+            crate::pipewire_registry::LinkState::Unlinked => {
+                pmx::pipewire::link::LinkState::Unlinked
+            }
+            crate::pipewire_registry::LinkState::Init => pmx::pipewire::link::LinkState::Init,
+            crate::pipewire_registry::LinkState::Negotiating => {
+                pmx::pipewire::link::LinkState::Negotiating
+            }
+            crate::pipewire_registry::LinkState::Allocating => {
+                pmx::pipewire::link::LinkState::Allocating
+            }
+            crate::pipewire_registry::LinkState::Paused => pmx::pipewire::link::LinkState::Paused,
+            crate::pipewire_registry::LinkState::Active => pmx::pipewire::link::LinkState::Active,
+        }
+    }
+}
+
+impl From<crate::pipewire_registry::Module> for ListModule {
+    fn from(m: crate::pipewire_registry::Module) -> Self {
+        ListModule {
+            global_id: m.global_id,
+            object_serial: m.object_serial,
+            name: m.name,
+        }
+    }
+}
+
+impl From<crate::pipewire_registry::Factory> for ListFactory {
+    fn from(f: crate::pipewire_registry::Factory) -> Self {
+        ListFactory {
+            global_id: f.global_id,
+            object_serial: f.object_serial,
+            module_id: f.module_id,
+            name: f.name,
+            type_name: f.type_name,
+            type_version: f.type_version,
+        }
+    }
+}
+
+impl From<crate::pipewire_registry::Metadata> for ListMetadata {
+    fn from(m: crate::pipewire_registry::Metadata) -> Self {
+        ListMetadata {
+            global_id: m.global_id,
+            object_serial: m.object_serial,
+            name: m.name,
+        }
+    }
+}
+
+impl From<crate::pipewire_registry::Core> for ListCore {
+    fn from(c: crate::pipewire_registry::Core) -> Self {
+        ListCore {
+            global_id: c.global_id,
+            object_serial: c.object_serial,
+            name: c.name,
+        }
+    }
+}
+
+impl From<crate::pipewire_registry::Node> for ListNode {
+    fn from(n: crate::pipewire_registry::Node) -> Self {
+        ListNode {
+            object_serial: n.object_serial,
+            factory_id: n.factory_id,
+            client_id: n.client_id,
+            client_api: n.client_api,
+            application_name: n.application_name,
+            name: n.node_name,
+            media_class: n.media_class,
+            global_id: n.global_id,
+            state: pmx::pipewire::node::NodeState::from(&n.state) as i32,
+            error: match n.state {
+                crate::pipewire_registry::NodeState::Error(error) => Some(error),
+                _ => None,
+            },
+            input_ports: n.input_ports,
+            output_ports: n.output_ports,
+        }
+    }
+}
+
+impl From<&crate::pipewire_registry::NodeState> for pmx::pipewire::node::NodeState {
+    fn from(state: &crate::pipewire_registry::NodeState) -> Self {
+        match state {
+            crate::pipewire_registry::NodeState::Unknown => pmx::pipewire::node::NodeState::Unknown,
+            crate::pipewire_registry::NodeState::Error(_) => pmx::pipewire::node::NodeState::Error,
+            crate::pipewire_registry::NodeState::Creating => {
+                pmx::pipewire::node::NodeState::Creating
+            }
+            crate::pipewire_registry::NodeState::Suspended => {
+                pmx::pipewire::node::NodeState::Suspended
+            }
+            crate::pipewire_registry::NodeState::Idle => pmx::pipewire::node::NodeState::Idle,
+            crate::pipewire_registry::NodeState::Running => pmx::pipewire::node::NodeState::Running,
+        }
+    }
+}
+
+impl From<crate::pipewire_registry::Device> for ListDevice {
+    fn from(d: crate::pipewire_registry::Device) -> Self {
+        ListDevice {
+            factory_id: d.factory_id,
+            object_serial: d.object_serial,
+            client_id: d.client_id,
+            name: d.name,
+            description: d.description,
+            nick: d.nick,
+            media_class: d.media_class,
+            global_id: d.global_id,
+        }
+    }
+}
+
+impl From<crate::pipewire_registry::Port> for ListPort {
+    fn from(p: crate::pipewire_registry::Port) -> Self {
+        ListPort {
+            id: p.id,
+            node_id: p.node_id,
+            name: p.name,
+            direction: match p.direction {
+                crate::pipewire_registry::PortDirection::In => {
+                    pmx::pipewire::port::PortDirection::In as i32
+                }
+                crate::pipewire_registry::PortDirection::Out => {
+                    pmx::pipewire::port::PortDirection::Out as i32
+                }
+                crate::pipewire_registry::PortDirection::Unknown => {
+                    pmx::pipewire::port::PortDirection::Unknown as i32
+                }
+            },
+            physical: p.physical,
+            alias: p.alias,
+            group: p.group,
+            path: p.path,
+            dsp_format: p.dsp_format,
+            audio_channel: p.audio_channel,
+            object_serial: p.object_serial,
+            global_id: p.global_id,
+        }
+    }
+}
+
+impl From<RegistryEntity> for pmx::pipewire::RegistryEntity {
+    fn from(entity: RegistryEntity) -> Self {
+        let entity = match entity {
+            RegistryEntity::Node(n) => registry_entity::Entity::Node(n.into()),
+            RegistryEntity::Port(p) => registry_entity::Entity::Port(p.into()),
+            RegistryEntity::Link(l) => registry_entity::Entity::Link(l.into()),
+            RegistryEntity::Device(d) => registry_entity::Entity::Device(d.into()),
+            RegistryEntity::Application(a) => registry_entity::Entity::Application(a.into()),
+            RegistryEntity::Module(m) => registry_entity::Entity::Module(m.into()),
+            RegistryEntity::Factory(f) => registry_entity::Entity::Factory(f.into()),
+            RegistryEntity::Metadata(m) => registry_entity::Entity::Metadata(m.into()),
+            RegistryEntity::Core(c) => registry_entity::Entity::Core(c.into()),
+        };
+        pmx::pipewire::RegistryEntity {
+            entity: Some(entity),
+        }
+    }
+}
+
+impl From<crate::pipewire_registry::RegistryEvent> for RegistryEvent {
+    fn from(event: crate::pipewire_registry::RegistryEvent) -> Self {
+        let event = match event {
+            crate::pipewire_registry::RegistryEvent::Snapshot(snapshot) => {
+                registry_event::Event::Snapshot(RegistrySnapshot {
+                    nodes: snapshot.nodes.into_iter().map(ListNode::from).collect(),
+                    ports: snapshot.ports.into_iter().map(ListPort::from).collect(),
+                    links: snapshot
+                        .links
+                        .into_iter()
+                        .map(pmx::pipewire::link::Link::from)
+                        .collect(),
+                    devices: snapshot.devices.into_iter().map(ListDevice::from).collect(),
+                    applications: snapshot
+                        .applications
+                        .into_iter()
+                        .map(ListApplication::from)
+                        .collect(),
+                    status: Some(snapshot.status.into()),
+                    modules: snapshot.modules.into_iter().map(ListModule::from).collect(),
+                    factories: snapshot
+                        .factories
+                        .into_iter()
+                        .map(ListFactory::from)
+                        .collect(),
+                    metadata: snapshot
+                        .metadata
+                        .into_iter()
+                        .map(ListMetadata::from)
+                        .collect(),
+                    cores: snapshot.cores.into_iter().map(ListCore::from).collect(),
+                })
+            }
+            crate::pipewire_registry::RegistryEvent::Added(entity) => {
+                registry_event::Event::Added(entity.into())
+            }
+            crate::pipewire_registry::RegistryEvent::Changed(entity) => {
+                registry_event::Event::Changed(entity.into())
+            }
+            crate::pipewire_registry::RegistryEvent::Removed(entity) => {
+                registry_event::Event::Removed(entity.into())
+            }
+            crate::pipewire_registry::RegistryEvent::PropertiesChanged {
+                global_id,
+                properties,
+            } => registry_event::Event::PropertiesChanged(pmx::pipewire::ObjectProperties {
+                global_id,
+                properties: properties.into_iter().collect(),
+            }),
+            crate::pipewire_registry::RegistryEvent::StatusChanged(status) => {
+                registry_event::Event::StatusChanged(status.into())
+            }
+        };
+        RegistryEvent { event: Some(event) }
+    }
+}
+
+impl From<crate::pipewire_registry::ConnectionState> for pmx::pipewire::ConnectionState {
+    fn from(connection_state: crate::pipewire_registry::ConnectionState) -> Self {
+        match connection_state {
+            crate::pipewire_registry::ConnectionState::Disconnected => {
+                pmx::pipewire::ConnectionState::Disconnected
+            }
+            crate::pipewire_registry::ConnectionState::Connected => {
+                pmx::pipewire::ConnectionState::Connected
+            }
+        }
+    }
+}
+
+impl From<crate::pipewire_registry::RegistryStatus> for pmx::pipewire::RegistryStatus {
+    fn from(status: crate::pipewire_registry::RegistryStatus) -> Self {
+        pmx::pipewire::RegistryStatus {
+            connection_state: pmx::pipewire::ConnectionState::from(status.connection_state) as i32,
+            ready: status.ready,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name_filter(pattern: name_pattern::Pattern) -> Option<pmx::pipewire::ListFilter> {
+        Some(pmx::pipewire::ListFilter {
+            name: Some(pmx::pipewire::NamePattern {
+                pattern: Some(pattern),
+            }),
+            ..pmx::pipewire::ListFilter::default()
+        })
+    }
+
+    #[test]
+    fn name_patterns_become_globs_or_regexes() {
+        let glob = list_filter(name_filter(name_pattern::Pattern::Glob(String::from(
+            "alsa_*",
+        ))))
+        .unwrap();
+        let regex = list_filter(name_filter(name_pattern::Pattern::Regex(String::from(
+            "^alsa_(in|out)put",
+        ))))
+        .unwrap();
+
+        assert!(matches!(glob.name, Some(NamePattern::Glob(_))));
+        assert!(glob.matches_name(&["alsa_output.usb"]));
+        assert!(!glob.matches_name(&["bluez_output"]));
+        assert!(matches!(regex.name, Some(NamePattern::Regex(_))));
+        assert!(regex.matches_name(&["alsa_input.pci"]));
+        assert!(!regex.matches_name(&["my_alsa_input"]));
+    }
+
+    #[test]
+    fn invalid_name_patterns_are_invalid_arguments() {
+        for pattern in [
+            name_pattern::Pattern::Glob(String::from("alsa_[")),
+            name_pattern::Pattern::Regex(String::from("alsa_(")),
+        ] {
+            let error = list_filter(name_filter(pattern)).unwrap_err();
+            assert_eq!(Status::from(error).code(), tonic::Code::InvalidArgument);
+        }
+    }
+
+    #[test]
+    fn unknown_port_directions_are_invalid_arguments() {
+        let filter = pmx::pipewire::ListFilter {
+            direction: Some(42),
+            ..pmx::pipewire::ListFilter::default()
+        };
+
+        let error = list_filter(Some(filter)).unwrap_err();
+        assert_eq!(Status::from(error).code(), tonic::Code::InvalidArgument);
+    }
+}
@@ -1,6 +1,12 @@
+use std::fs;
+use std::os::unix::fs::FileTypeExt;
+
+use anyhow::{Context, Result};
 use fr_logging::Logger;
+use tokio::net::UnixListener;
 use tonic::transport::Server;
 
+use crate::config::ListenAddress;
 use crate::grpc_services::PipewireService;
 
 use crate::pipewire_factory::PipewireFactoryRequest;
@@ -8,29 +14,53 @@ use crate::pipewire_registry::PipewireRegistryRequests;
 
 pub fn run_grpc_service(
     logger: &Logger,
+    listen_address: ListenAddress,
     request_sender: tokio::sync::mpsc::UnboundedSender<PipewireRegistryRequests>,
     pipewire_factory_request_sender: pipewire::channel::Sender<PipewireFactoryRequest>,
-) {
+) -> Result<()> {
     tokio::runtime::Builder::new_current_thread()
         .enable_all()
         .build()
-        .unwrap()
+        .context("Failed to start the gRPC runtime")?
         .block_on(async move {
             logger.log_info("Starting RPC services");
-            let addr = match "127.0.0.1:50000".parse() {
-                Ok(addr) => addr,
-                Err(error) => {
-                    panic!("{error:#?}");
+
+            let router = Server::builder().add_service(PipewireService::new_server(
+                request_sender,
+                pipewire_factory_request_sender,
+            ));
+
+            match listen_address {
+                ListenAddress::Tcp(addr) => {
+                    logger.log_info(&format!("Listening on {addr}"));
+                    router
+                        .serve(addr)
+                        .await
+                        .with_context(|| format!("Failed to serve gRPC on {addr}"))
                 }
-            };
+                ListenAddress::Unix(path) => {
+                    // A socket left behind by an earlier run would make the
+                    // bind fail, anything else at that path is not ours.
+                    if let Ok(metadata) = fs::symlink_metadata(&path) {
+                        if metadata.file_type().is_socket() {
+                            fs::remove_file(&path).with_context(|| {
+                                format!("Failed to remove stale socket {}", path.display())
+                            })?;
+                        }
+                    }
+                    let listener = UnixListener::bind(&path)
+                        .with_context(|| format!("Failed to listen on unix:{}", path.display()))?;
+                    logger.log_info(&format!("Listening on unix:{}", path.display()));
 
-            Server::builder()
-                .add_service(PipewireService::new_server(
-                    request_sender,
-                    pipewire_factory_request_sender,
-                ))
-                .serve(addr)
-                .await
-                .unwrap();
-        });
+                    let incoming = futures::stream::unfold(listener, |listener| async move {
+                        let connection = listener.accept().await.map(|(stream, _)| stream);
+                        Some((connection, listener))
+                    });
+                    router
+                        .serve_with_incoming(incoming)
+                        .await
+                        .with_context(|| format!("Failed to serve gRPC on unix:{}", path.display()))
+                }
+            }
+        })
 }
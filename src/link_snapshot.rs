@@ -0,0 +1,56 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::pipewire_registry::DesiredLink;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotFormat {
+    Toml,
+    Json,
+}
+
+/// The links of the graph by node and port name, so they can be restored
+/// after the ids changed. The client reads and writes the snapshot files,
+/// the server only sees their content.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct LinkSnapshot {
+    #[serde(default)]
+    pub links: Vec<DesiredLink>,
+}
+
+impl LinkSnapshot {
+    /// Parses a snapshot. Errors only tell where the content is invalid,
+    /// they do not repeat any of it.
+    pub fn parse(content: &str, format: SnapshotFormat) -> Result<LinkSnapshot> {
+        match format {
+            SnapshotFormat::Toml => toml::from_str(content).map_err(|error| {
+                let line = error.span().map(|span| {
+                    content.as_bytes()[..span.start]
+                        .iter()
+                        .filter(|byte| **byte == b'\n')
+                        .count()
+                        + 1
+                });
+                match line {
+                    Some(line) => anyhow!("Invalid TOML snapshot at line {line}"),
+                    None => anyhow!("Invalid TOML snapshot"),
+                }
+            }),
+            SnapshotFormat::Json => serde_json::from_str(content).map_err(|error| {
+                anyhow!(
+                    "Invalid JSON snapshot at line {} column {}",
+                    error.line(),
+                    error.column()
+                )
+            }),
+        }
+    }
+
+    pub fn serialize(&self, format: SnapshotFormat) -> Result<String> {
+        match format {
+            SnapshotFormat::Toml => toml::to_string_pretty(self).map_err(anyhow::Error::from),
+            SnapshotFormat::Json => serde_json::to_string_pretty(self).map_err(anyhow::Error::from),
+        }
+        .context("Failed to serialize snapshot")
+    }
+}
@@ -1,20 +1,40 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Result};
+
+use fr_logging::Logger;
+
+use libspa::param::audio::AudioInfoRaw;
+use libspa::param::format::{MediaSubtype, MediaType};
+use libspa::param::format_utils;
+use libspa::pod::Pod;
 use libspa::utils::dict::DictRef;
+use pipewire::link::{LinkInfoRef, LinkState};
+use pipewire::node::{NodeInfoRef, NodeState};
 use pipewire::registry::GlobalObject;
+use pipewire::types::ObjectType;
 
 use tokio::sync::mpsc::UnboundedSender as Sender;
 
+use crate::config::ObjectFilters;
+use crate::pipewire_registry;
+
 pub enum PipewireUpdateEvent {
     Device {
+        global_id: u32,
         name: String,
-        factory_id: String,
-        client_id: String,
+        factory_id: u32,
+        client_id: u32,
         description: String,
         nick: String,
         media_class: String,
-        object_serial: String,
+        object_serial: u64,
     },
     Port {
-        id: String,
+        global_id: u32,
+        id: u32,
         name: String,
         direction: String,
         physical: String,
@@ -22,22 +42,24 @@ pub enum PipewireUpdateEvent {
         group: String,
         path: String,
         dsp_format: String,
-        node_id: String,
+        node_id: u32,
         audio_channel: String,
-        object_serial: String,
+        object_serial: u64,
     },
     Node {
-        object_serial: String,
-        factory_id: String,
-        client_id: String,
+        global_id: u32,
+        object_serial: u64,
+        factory_id: Option<u32>,
+        client_id: Option<u32>,
         client_api: String,
         application_name: String,
         node_name: String,
         media_class: String,
     },
     Application {
-        object_serial: String,
-        module_id: String,
+        global_id: u32,
+        object_serial: u64,
+        module_id: Option<u32>,
         pipewire_protocol: String,
         pipewire_sec_pid: String,
         pipewire_sec_uid: String,
@@ -47,112 +69,434 @@ pub enum PipewireUpdateEvent {
         name: String,
     },
     Link {
-        object_serial: String,
-        factory_id: String,
-        client_id: String,
-        output_port_id: String,
-        input_port_id: String,
-        output_node_id: String,
-        input_node_id: String,
+        global_id: u32,
+        object_serial: u64,
+        factory_id: u32,
+        client_id: Option<u32>,
+        output_port_id: u32,
+        input_port_id: u32,
+        output_node_id: u32,
+        input_node_id: u32,
+    },
+    Module {
+        global_id: u32,
+        object_serial: u64,
+        name: String,
+    },
+    Factory {
+        global_id: u32,
+        object_serial: u64,
+        module_id: Option<u32>,
+        name: String,
+        type_name: String,
+        type_version: u32,
+    },
+    Metadata {
+        global_id: u32,
+        object_serial: u64,
+        name: String,
     },
+    Core {
+        global_id: u32,
+        object_serial: u64,
+        name: String,
+    },
+    Properties {
+        global_id: u32,
+        properties: BTreeMap<String, String>,
+    },
+    NodeInfo {
+        global_id: u32,
+        state: pipewire_registry::NodeState,
+        input_ports: u32,
+        output_ports: u32,
+        properties: Option<BTreeMap<String, String>>,
+    },
+    LinkInfo {
+        global_id: u32,
+        state: pipewire_registry::LinkState,
+        format: Option<String>,
+        properties: Option<BTreeMap<String, String>>,
+    },
+    ObjectInfo {
+        global_id: u32,
+        properties: BTreeMap<String, String>,
+    },
+    GlobalRemoved {
+        global_id: u32,
+    },
+    Connected,
+    Synced,
+    Disconnected,
 }
 
 pub struct PipewireEventConsumer {
+    logger: Logger,
+    filters: ObjectFilters,
+    ignored_nodes: RefCell<BTreeSet<u32>>,
+    unhandled_globals: RefCell<BTreeMap<String, u64>>,
     pipewire_update_event_sender: Sender<PipewireUpdateEvent>,
 }
 
 impl PipewireEventConsumer {
-    pub fn new(pipewire_update_event_sender: Sender<PipewireUpdateEvent>) -> PipewireEventConsumer {
+    pub fn new(
+        logger: Logger,
+        filters: ObjectFilters,
+        pipewire_update_event_sender: Sender<PipewireUpdateEvent>,
+    ) -> PipewireEventConsumer {
         PipewireEventConsumer {
+            logger,
+            filters,
+            ignored_nodes: RefCell::new(BTreeSet::new()),
+            unhandled_globals: RefCell::new(BTreeMap::new()),
             pipewire_update_event_sender,
         }
     }
 
     pub fn process_pipewire_update(&self, update: &GlobalObject<&DictRef>) {
-        if let Some(props) = update.props {
-            if props.keys().any(|p| p == "link.output.port") {
-                self.pipewire_update_event_sender
-                    .send(PipewireUpdateEvent::Link {
-                        object_serial: String::from(props.get("object.serial").unwrap()),
-                        factory_id: String::from(props.get("factory.id").unwrap()),
-                        client_id: String::from(props.get("client.id").unwrap_or("")),
-                        output_port_id: String::from(props.get("link.output.port").unwrap()),
-                        input_port_id: String::from(props.get("link.input.port").unwrap()),
-                        output_node_id: String::from(props.get("link.output.node").unwrap()),
-                        input_node_id: String::from(props.get("link.input.node").unwrap()),
-                    })
-                    .unwrap();
-                return;
+        let event = match create_update_event(update) {
+            Ok(Some(event)) => {
+                if self.is_filtered(&event) {
+                    return;
+                }
+                Some(event)
             }
-
-            if let Some(device_name) = props.get("device.name") {
-                self.pipewire_update_event_sender
-                    .send(PipewireUpdateEvent::Device {
-                        name: String::from(device_name),
-                        factory_id: String::from(props.get("factory.id").unwrap()),
-                        client_id: String::from(props.get("client.id").unwrap()),
-                        description: String::from(props.get("device.description").unwrap()),
-                        nick: String::from(props.get("device.nick").unwrap_or("None")),
-                        media_class: String::from(props.get("media.class").unwrap()),
-                        object_serial: String::from(props.get("object.serial").unwrap()),
-                    })
-                    .unwrap();
-                return;
-            };
-
-            if let Some(_value) = props.get("port.name") {
-                self.pipewire_update_event_sender
-                    .send(PipewireUpdateEvent::Port {
-                        object_serial: String::from(props.get("object.serial").unwrap()),
-                        id: String::from(props.get("port.id").unwrap()),
-                        name: String::from(props.get("port.name").unwrap()),
-                        direction: String::from(props.get("port.direction").unwrap()),
-                        physical: String::from(props.get("port.physical").unwrap_or("")),
-                        alias: String::from(props.get("port.alias").unwrap()),
-                        group: String::from(props.get("port.group").unwrap_or("")),
-                        path: String::from(props.get("object.path").unwrap()),
-                        dsp_format: String::from(props.get("format.dsp").unwrap_or("None")),
-                        node_id: String::from(props.get("node.id").unwrap()),
-                        audio_channel: String::from(props.get("audio.channel").unwrap_or("")),
-                    })
-                    .unwrap();
-                return;
-            };
-
-            if let Some(_value) = props.get("node.name") {
-                self.pipewire_update_event_sender
-                    .send(PipewireUpdateEvent::Node {
-                        object_serial: String::from(props.get("object.serial").unwrap()),
-                        factory_id: String::from(props.get("factory.id").unwrap_or("")),
-                        client_id: String::from(props.get("client.id").unwrap_or("")),
-                        client_api: String::from(props.get("client.api").unwrap_or("")),
-                        application_name: String::from(props.get("application.name").unwrap_or("")),
-                        node_name: String::from(props.get("node.name").unwrap_or("")),
-                        media_class: String::from(props.get(" media.class").unwrap_or("")),
-                    })
-                    .unwrap();
-                return;
+            Ok(None) => {
+                self.count_unhandled(update);
+                None
             }
+            Err(error) => {
+                self.logger.log_info(&format!(
+                    "Ignoring {} global {}: {error:#}",
+                    update.type_.to_str(),
+                    update.id
+                ));
+                None
+            }
+        };
+
+        // The full dictionary is kept for every global, including the ones
+        // without a typed representation.
+        if let Some(props) = update.props {
+            self.pipewire_update_event_sender
+                .send(PipewireUpdateEvent::Properties {
+                    global_id: update.id,
+                    properties: collect_properties(props),
+                })
+                .unwrap();
+        }
+        if let Some(event) = event {
+            self.pipewire_update_event_sender.send(event).unwrap();
+        }
+    }
+
+    pub fn process_node_info(&self, global_id: u32, info: &NodeInfoRef) {
+        let state = match info.state() {
+            NodeState::Error(message) => pipewire_registry::NodeState::Error(String::from(message)),
+            NodeState::Creating => pipewire_registry::NodeState::Creating,
+            NodeState::Suspended => pipewire_registry::NodeState::Suspended,
+            NodeState::Idle => pipewire_registry::NodeState::Idle,
+            NodeState::Running => pipewire_registry::NodeState::Running,
+        };
+        self.pipewire_update_event_sender
+            .send(PipewireUpdateEvent::NodeInfo {
+                global_id,
+                state,
+                input_ports: info.n_input_ports(),
+                output_ports: info.n_output_ports(),
+                properties: info.props().map(collect_properties),
+            })
+            .unwrap();
+    }
+
+    pub fn process_link_info(&self, global_id: u32, info: &LinkInfoRef) {
+        let state = match info.state() {
+            LinkState::Error(message) => pipewire_registry::LinkState::Error(String::from(message)),
+            LinkState::Unlinked => pipewire_registry::LinkState::Unlinked,
+            LinkState::Init => pipewire_registry::LinkState::Init,
+            LinkState::Negotiating => pipewire_registry::LinkState::Negotiating,
+            LinkState::Allocating => pipewire_registry::LinkState::Allocating,
+            LinkState::Paused => pipewire_registry::LinkState::Paused,
+            LinkState::Active => pipewire_registry::LinkState::Active,
+        };
+        self.pipewire_update_event_sender
+            .send(PipewireUpdateEvent::LinkInfo {
+                global_id,
+                state,
+                format: info.format().and_then(describe_format),
+                properties: info.props().map(collect_properties),
+            })
+            .unwrap();
+    }
+
+    pub fn process_object_info(&self, global_id: u32, props: Option<&DictRef>) {
+        if let Some(props) = props {
+            self.pipewire_update_event_sender
+                .send(PipewireUpdateEvent::ObjectInfo {
+                    global_id,
+                    properties: collect_properties(props),
+                })
+                .unwrap();
+        }
+    }
+
+    pub fn process_bind_error(&self, global_id: u32, error: anyhow::Error) {
+        self.logger
+            .log_info(&format!("Failed to bind global {global_id}: {error:#}"));
+    }
+
+    fn count_unhandled(&self, update: &GlobalObject<&DictRef>) {
+        *self
+            .unhandled_globals
+            .borrow_mut()
+            .entry(String::from(update.type_.to_str()))
+            .or_insert(0) += 1;
+    }
+
+    pub fn process_pipewire_remove(&self, global_id: u32) {
+        self.ignored_nodes.borrow_mut().remove(&global_id);
+        self.pipewire_update_event_sender
+            .send(PipewireUpdateEvent::GlobalRemoved { global_id })
+            .unwrap();
+    }
 
-            if let Some(_value) = props.get("application.name") {
-                let application = PipewireUpdateEvent::Application {
-                    object_serial: String::from(props.get("object.serial").unwrap()),
-                    module_id: String::from(props.get("module.id").unwrap_or("")),
-                    pipewire_protocol: String::from(props.get("pipewire.protocol").unwrap_or("")),
-                    pipewire_sec_pid: String::from(props.get("pipewire.sec.pid").unwrap_or("")),
-                    pipewire_sec_uid: String::from(props.get("pipewire.sec.uid").unwrap_or("")),
-                    pipewire_sec_gid: String::from(props.get("pipewire.sec.gid").unwrap_or("")),
-                    pipewire_sec_socket: String::from(
-                        props.get("pipewire.sec.socket").unwrap_or(""),
-                    ),
-                    pipewire_access: String::from(props.get("pipewire.access").unwrap_or("")),
-                    name: String::from(props.get("application.name").unwrap_or("")),
-                };
-                self.pipewire_update_event_sender.send(application).unwrap();
-                return;
+    pub fn process_connected(&self) {
+        self.pipewire_update_event_sender
+            .send(PipewireUpdateEvent::Connected)
+            .unwrap();
+    }
+
+    /// Globals of types the registry has no use for are only counted, a
+    /// single line after the initial sync shows what was skipped.
+    pub fn process_synced(&self) {
+        let unhandled_globals = std::mem::take(&mut *self.unhandled_globals.borrow_mut());
+        if !unhandled_globals.is_empty() {
+            let counts: Vec<String> = unhandled_globals
+                .iter()
+                .map(|(type_name, count)| format!("{count} {type_name}"))
+                .collect();
+            self.logger
+                .log_info(&format!("Unhandled globals: {}", counts.join(", ")));
+        }
+        self.pipewire_update_event_sender
+            .send(PipewireUpdateEvent::Synced)
+            .unwrap();
+    }
+
+    pub fn process_disconnected(&self) {
+        self.ignored_nodes.borrow_mut().clear();
+        self.unhandled_globals.borrow_mut().clear();
+        self.pipewire_update_event_sender
+            .send(PipewireUpdateEvent::Disconnected)
+            .unwrap();
+    }
+
+    fn is_filtered(&self, event: &PipewireUpdateEvent) -> bool {
+        let mut ignored_nodes = self.ignored_nodes.borrow_mut();
+        match event {
+            PipewireUpdateEvent::Node {
+                global_id,
+                node_name,
+                media_class,
+                ..
+            } => {
+                if self.filters.ignores_node(node_name, media_class) {
+                    ignored_nodes.insert(*global_id);
+                    true
+                } else {
+                    ignored_nodes.remove(global_id);
+                    false
+                }
             }
+            PipewireUpdateEvent::Port { node_id, .. } => ignored_nodes.contains(node_id),
+            PipewireUpdateEvent::Link {
+                output_node_id,
+                input_node_id,
+                ..
+            } => ignored_nodes.contains(output_node_id) || ignored_nodes.contains(input_node_id),
+            PipewireUpdateEvent::Application { name, .. } => self.filters.ignores_application(name),
+            _ => false,
+        }
+    }
+}
+
+fn create_update_event(update: &GlobalObject<&DictRef>) -> Result<Option<PipewireUpdateEvent>> {
+    let props = || {
+        update
+            .props
+            .ok_or_else(|| anyhow!("global has no properties"))
+    };
+    let event = match update.type_ {
+        ObjectType::Link => link_event(update.id, props()?)?,
+        ObjectType::Device => device_event(update.id, props()?)?,
+        ObjectType::Port => port_event(update.id, props()?)?,
+        ObjectType::Node => node_event(update.id, props()?)?,
+        ObjectType::Client => application_event(update.id, props()?)?,
+        ObjectType::Module => module_event(update.id, props()?)?,
+        ObjectType::Factory => factory_event(update.id, props()?)?,
+        ObjectType::Metadata => metadata_event(update.id, props()?)?,
+        ObjectType::Core => core_event(update.id, props()?)?,
+        _ => return Ok(None),
+    };
+    Ok(Some(event))
+}
+
+fn link_event(global_id: u32, props: &DictRef) -> Result<PipewireUpdateEvent> {
+    Ok(PipewireUpdateEvent::Link {
+        global_id,
+        object_serial: parse(props, "object.serial")?,
+        factory_id: parse(props, "factory.id")?,
+        client_id: parse_optional(props, "client.id")?,
+        output_port_id: parse(props, "link.output.port")?,
+        input_port_id: parse(props, "link.input.port")?,
+        output_node_id: parse(props, "link.output.node")?,
+        input_node_id: parse(props, "link.input.node")?,
+    })
+}
+
+fn device_event(global_id: u32, props: &DictRef) -> Result<PipewireUpdateEvent> {
+    Ok(PipewireUpdateEvent::Device {
+        global_id,
+        name: required(props, "device.name")?,
+        factory_id: parse(props, "factory.id")?,
+        client_id: parse(props, "client.id")?,
+        description: required(props, "device.description")?,
+        nick: String::from(props.get("device.nick").unwrap_or("None")),
+        media_class: required(props, "media.class")?,
+        object_serial: parse(props, "object.serial")?,
+    })
+}
+
+fn port_event(global_id: u32, props: &DictRef) -> Result<PipewireUpdateEvent> {
+    Ok(PipewireUpdateEvent::Port {
+        global_id,
+        object_serial: parse(props, "object.serial")?,
+        id: parse(props, "port.id")?,
+        name: required(props, "port.name")?,
+        direction: required(props, "port.direction")?,
+        physical: String::from(props.get("port.physical").unwrap_or("")),
+        alias: required(props, "port.alias")?,
+        group: String::from(props.get("port.group").unwrap_or("")),
+        path: required(props, "object.path")?,
+        dsp_format: String::from(props.get("format.dsp").unwrap_or("None")),
+        node_id: parse(props, "node.id")?,
+        audio_channel: String::from(props.get("audio.channel").unwrap_or("")),
+    })
+}
+
+fn node_event(global_id: u32, props: &DictRef) -> Result<PipewireUpdateEvent> {
+    Ok(PipewireUpdateEvent::Node {
+        global_id,
+        object_serial: parse(props, "object.serial")?,
+        factory_id: parse_optional(props, "factory.id")?,
+        client_id: parse_optional(props, "client.id")?,
+        client_api: String::from(props.get("client.api").unwrap_or("")),
+        application_name: String::from(props.get("application.name").unwrap_or("")),
+        node_name: String::from(props.get("node.name").unwrap_or("")),
+        media_class: String::from(props.get("media.class").unwrap_or("")),
+    })
+}
+
+fn application_event(global_id: u32, props: &DictRef) -> Result<PipewireUpdateEvent> {
+    Ok(PipewireUpdateEvent::Application {
+        global_id,
+        object_serial: parse(props, "object.serial")?,
+        module_id: parse_optional(props, "module.id")?,
+        pipewire_protocol: String::from(props.get("pipewire.protocol").unwrap_or("")),
+        pipewire_sec_pid: String::from(props.get("pipewire.sec.pid").unwrap_or("")),
+        pipewire_sec_uid: String::from(props.get("pipewire.sec.uid").unwrap_or("")),
+        pipewire_sec_gid: String::from(props.get("pipewire.sec.gid").unwrap_or("")),
+        pipewire_sec_socket: String::from(props.get("pipewire.sec.socket").unwrap_or("")),
+        pipewire_access: String::from(props.get("pipewire.access").unwrap_or("")),
+        name: String::from(props.get("application.name").unwrap_or("")),
+    })
+}
+
+fn module_event(global_id: u32, props: &DictRef) -> Result<PipewireUpdateEvent> {
+    Ok(PipewireUpdateEvent::Module {
+        global_id,
+        object_serial: parse(props, "object.serial")?,
+        name: required(props, "module.name")?,
+    })
+}
 
-            println!("{props:#?}");
+fn factory_event(global_id: u32, props: &DictRef) -> Result<PipewireUpdateEvent> {
+    Ok(PipewireUpdateEvent::Factory {
+        global_id,
+        object_serial: parse(props, "object.serial")?,
+        module_id: parse_optional(props, "module.id")?,
+        name: required(props, "factory.name")?,
+        type_name: required(props, "factory.type.name")?,
+        type_version: parse(props, "factory.type.version")?,
+    })
+}
+
+fn metadata_event(global_id: u32, props: &DictRef) -> Result<PipewireUpdateEvent> {
+    Ok(PipewireUpdateEvent::Metadata {
+        global_id,
+        object_serial: parse(props, "object.serial")?,
+        name: String::from(props.get("metadata.name").unwrap_or("")),
+    })
+}
+
+fn core_event(global_id: u32, props: &DictRef) -> Result<PipewireUpdateEvent> {
+    Ok(PipewireUpdateEvent::Core {
+        global_id,
+        object_serial: parse(props, "object.serial")?,
+        name: String::from(props.get("core.name").unwrap_or("")),
+    })
+}
+
+/// Renders a negotiated format pod as e.g. `audio/raw F32LE 48000Hz 2ch`.
+fn describe_format(format: &Pod) -> Option<String> {
+    let (media_type, media_subtype) = format_utils::parse_format(format).ok()?;
+    if media_type == MediaType::Audio && media_subtype == MediaSubtype::Raw {
+        let mut audio_info = AudioInfoRaw::new();
+        if audio_info.parse(format).is_ok() {
+            return Some(format!(
+                "audio/raw {:?} {}Hz {}ch",
+                audio_info.format(),
+                audio_info.rate(),
+                audio_info.channels()
+            ));
         }
     }
+    Some(format!("{media_type:?}/{media_subtype:?}"))
+}
+
+fn collect_properties(props: &DictRef) -> BTreeMap<String, String> {
+    props
+        .iter()
+        .map(|(key, value)| (String::from(key), String::from(value)))
+        .collect()
+}
+
+fn required(props: &DictRef, key: &str) -> Result<String> {
+    props
+        .get(key)
+        .map(String::from)
+        .ok_or_else(|| anyhow!("missing property {key}"))
+}
+
+fn parse<T>(props: &DictRef, key: &str) -> Result<T>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    parse_optional(props, key)?.ok_or_else(|| anyhow!("missing property {key}"))
+}
+
+fn parse_optional<T>(props: &DictRef, key: &str) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    props
+        .get(key)
+        .map(|value| {
+            value
+                .parse()
+                .with_context(|| format!("invalid value {value:?} for property {key}"))
+        })
+        .transpose()
 }
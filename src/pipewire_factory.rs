@@ -1,6 +1,13 @@
-use pipewire::core::Core;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use anyhow::{anyhow, Result};
 
-use crate::pipewire_loop::Factories;
+use libspa::utils::dict::DictRef;
+use pipewire::core::Core;
+use pipewire::registry::{GlobalObject, Registry};
+use pipewire::types::ObjectType;
 
 #[derive(Debug)]
 pub enum PipewireFactoryRequest {
@@ -9,60 +16,114 @@ pub enum PipewireFactoryRequest {
         input_port_id: String,
         output_node_id: String,
         input_node_id: String,
+        reply_sender: tokio::sync::oneshot::Sender<Result<()>>,
     },
-    CreateLinkByNodeName {
-        output_port_id: String,
-        input_port_id: String,
-        output_node_name: String,
-        input_node_name: String,
+    DestroyLink {
+        link_id: u32,
+        reply_sender: tokio::sync::oneshot::Sender<Result<()>>,
     },
 }
 
+#[derive(Debug, Clone)]
+pub struct Factory {
+    pub name: String,
+    pub type_name: String,
+}
+
+/// The factories announced by PipeWire, keyed by their global id so they
+/// can be dropped again when the global goes away.
+#[derive(Debug, Default)]
+pub struct Factories {
+    factories: BTreeMap<u32, Factory>,
+}
+
+impl Factories {
+    pub fn process_global(&mut self, global: &GlobalObject<&DictRef>) {
+        if global.type_ != ObjectType::Factory {
+            return;
+        }
+        let Some(props) = global.props else {
+            return;
+        };
+        if let (Some(name), Some(type_name)) =
+            (props.get("factory.name"), props.get("factory.type.name"))
+        {
+            self.factories.insert(
+                global.id,
+                Factory {
+                    name: String::from(name),
+                    type_name: String::from(type_name),
+                },
+            );
+        }
+    }
+
+    pub fn remove(&mut self, global_id: u32) -> Option<Factory> {
+        self.factories.remove(&global_id)
+    }
+
+    /// Returns the name of a factory creating objects of the given type.
+    pub fn find(&self, object_type: &ObjectType) -> Option<&str> {
+        let type_name = object_type.to_str();
+        self.factories
+            .values()
+            .find(|factory| factory.type_name == type_name)
+            .map(|factory| factory.name.as_str())
+    }
+
+    pub fn require(&self, object_type: &ObjectType) -> Result<&str> {
+        self.find(object_type)
+            .ok_or_else(|| anyhow!("PipeWire has no factory for {}", object_type.to_str()))
+    }
+}
+
 pub struct PipewireFactory {
-    pub factories: Factories,
+    pub factories: Rc<RefCell<Factories>>,
     pub core: Core,
+    pub registry: Rc<Registry>,
 }
 
 impl PipewireFactory {
     pub fn process_command(&self, request: PipewireFactoryRequest) {
         match request {
-            PipewireFactoryRequest::CreateLinkByNodeName {
-                output_port_id,
-                input_port_id,
-                output_node_name,
-                input_node_name,
-            } => {
-                self.core
-                    .create_object::<pipewire::link::Link>(
-                        &self.factories.link,
-                        &pipewire::properties::properties! {
-                                    "link.output.port" => output_port_id,
-                                    "link.input.port" => input_port_id,
-                                    "link.output.node" => output_node_name,
-                                    "link.input.node" => input_node_name,
-                                    "object.linger" => "1"
-                        },
-                    )
-                    .unwrap();
-            }
             PipewireFactoryRequest::CreateLink {
                 output_port_id,
                 input_port_id,
                 output_node_id,
                 input_node_id,
+                reply_sender,
             } => {
-                self.core
-                    .create_object::<pipewire::link::Link>(
-                        &self.factories.link,
-                        &pipewire::properties::properties! {
+                let factories = self.factories.borrow();
+                let result = factories
+                    .require(&ObjectType::Link)
+                    .and_then(|factory_name| {
+                        self.core
+                            .create_object::<pipewire::link::Link>(
+                                factory_name,
+                                &pipewire::properties::properties! {
                                     "link.output.port" => output_port_id,
                                     "link.input.port" => input_port_id,
                                     "link.output.node" => output_node_id,
                                     "link.input.node" => input_node_id,
                                     "object.linger" => "1"
-                        },
-                    )
-                    .unwrap();
+                                },
+                            )
+                            .map(|_| ())
+                            .map_err(anyhow::Error::from)
+                    });
+                let _ = reply_sender.send(result);
+            }
+            PipewireFactoryRequest::DestroyLink {
+                link_id,
+                reply_sender,
+            } => {
+                let result = self
+                    .registry
+                    .destroy_global(link_id)
+                    .into_result()
+                    .map(|_| ())
+                    .map_err(anyhow::Error::from);
+                let _ = reply_sender.send(result);
             }
         }
     }
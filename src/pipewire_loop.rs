@@ -1,89 +1,286 @@
-use std::cell::OnceCell;
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
 use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
 
 use anyhow::Result;
 
 use fr_logging::Logger;
 
+use libspa::utils::dict::DictRef;
+use libspa::utils::result::AsyncSeq;
+
 use pipewire::context::Context;
+use pipewire::core::{Core, PW_ID_CORE};
+use pipewire::device::{Device, DeviceListener};
+use pipewire::link::{Link, LinkListener};
 use pipewire::main_loop::MainLoop;
-use pipewire::registry::Registry;
+use pipewire::node::{Node, NodeListener};
+use pipewire::registry::{GlobalObject, Registry};
 use pipewire::types::ObjectType;
 
 use tokio::sync::mpsc::UnboundedSender as Sender;
 
+use crate::config::ServerConfig;
 use crate::pipewire_event_consumer::PipewireEventConsumer;
 use crate::pipewire_event_consumer::PipewireUpdateEvent;
-use crate::pipewire_factory::PipewireFactory;
-use crate::pipewire_factory::PipewireFactoryRequest;
+use crate::pipewire_factory::{Factories, PipewireFactory, PipewireFactoryRequest};
+
+const MAX_RECONNECT_INTERVAL: Duration = Duration::from_secs(30);
+const REQUIRED_FACTORIES: [ObjectType; 1] = [ObjectType::Link];
+
+/// A proxy bound to a global, together with the listener forwarding its
+/// info events.
+enum BoundObject {
+    Node {
+        _proxy: Node,
+        _listener: NodeListener,
+    },
+    Link {
+        _proxy: Link,
+        _listener: LinkListener,
+    },
+    Device {
+        _proxy: Device,
+        _listener: DeviceListener,
+    },
+}
 
 pub fn run_pipewire_loop(
     logger: &Logger,
+    consumer_logger: Logger,
+    config: &ServerConfig,
     pipewire_update_event_sender: Sender<PipewireUpdateEvent>,
-    pipewire_factory_request_receiver: pipewire::channel::Receiver<PipewireFactoryRequest>,
+    mut pipewire_factory_request_receiver: pipewire::channel::Receiver<PipewireFactoryRequest>,
 ) -> Result<()> {
     logger.log_info("Starting Pipewire Loop");
     pipewire::init();
     let main_loop = MainLoop::new(None)?;
     let context = Context::new(&main_loop)?;
-    let core = context.connect(None)?;
-    let registry = core.get_registry()?;
 
-    let consumer = PipewireEventConsumer::new(pipewire_update_event_sender);
+    let consumer = Rc::new(PipewireEventConsumer::new(
+        consumer_logger,
+        config.filters.clone(),
+        pipewire_update_event_sender,
+    ));
+
+    let mut core = connect(logger, &context, config, config.startup.wait_for_pipewire)?;
+    loop {
+        logger.log_info("Connected to PipeWire");
+        consumer.process_connected();
+        pipewire_factory_request_receiver = run_connection(
+            logger,
+            &main_loop,
+            core,
+            consumer.clone(),
+            pipewire_factory_request_receiver,
+        )?;
+        consumer.process_disconnected();
+
+        logger.log_info("Lost connection to PipeWire, reconnecting");
+        core = connect(logger, &context, config, true)?;
+        pipewire_factory_request_receiver =
+            drop_queued_requests(&main_loop, pipewire_factory_request_receiver);
+    }
+}
+
+/// Drops the factory requests that were sent while PipeWire was away. They
+/// name objects of the old connection, and their callers see the dropped
+/// reply as PipeWire being unavailable instead of waiting for the new one.
+fn drop_queued_requests(
+    main_loop: &MainLoop,
+    pipewire_factory_request_receiver: pipewire::channel::Receiver<PipewireFactoryRequest>,
+) -> pipewire::channel::Receiver<PipewireFactoryRequest> {
+    let receiver = pipewire_factory_request_receiver
+        .attach(main_loop.loop_(), |_request: PipewireFactoryRequest| {});
+    main_loop.loop_().iterate(Duration::ZERO);
+    receiver.deattach()
+}
+
+/// Runs the main loop for a single PipeWire connection until the core
+/// reports an error, and hands the factory request receiver back so it can
+/// be attached to the next connection.
+fn run_connection(
+    logger: &Logger,
+    main_loop: &MainLoop,
+    core: Core,
+    consumer: Rc<PipewireEventConsumer>,
+    pipewire_factory_request_receiver: pipewire::channel::Receiver<PipewireFactoryRequest>,
+) -> Result<pipewire::channel::Receiver<PipewireFactoryRequest>> {
+    let disconnect_reason: Rc<RefCell<Option<String>>> = Rc::new(RefCell::new(None));
+    let disconnect_reason_clone = disconnect_reason.clone();
+    let initial_sync: Rc<Cell<Option<AsyncSeq>>> = Rc::new(Cell::new(None));
+    let initial_sync_clone = initial_sync.clone();
+    let sync_consumer = consumer.clone();
+    let sync_main_loop_weak = main_loop.downgrade();
+    let main_loop_weak = main_loop.downgrade();
+    let core_listener = core
+        .add_listener_local()
+        .done(move |id, seq| {
+            if id == PW_ID_CORE && initial_sync_clone.get() == Some(seq) {
+                initial_sync_clone.set(None);
+                sync_consumer.process_synced();
+                if let Some(main_loop) = sync_main_loop_weak.upgrade() {
+                    main_loop.quit();
+                }
+            }
+        })
+        .error(move |id, _seq, res, message| {
+            if id == PW_ID_CORE {
+                *disconnect_reason_clone.borrow_mut() = Some(format!("{message} ({res})"));
+                if let Some(main_loop) = main_loop_weak.upgrade() {
+                    main_loop.quit();
+                }
+            }
+        })
+        .register();
+
+    let factories: Rc<RefCell<Factories>> = Rc::new(RefCell::new(Factories::default()));
+    let global_factories = factories.clone();
+    let remove_factories = factories.clone();
+    let bound_objects: Rc<RefCell<BTreeMap<u32, BoundObject>>> =
+        Rc::new(RefCell::new(BTreeMap::new()));
+    let global_bound_objects = bound_objects.clone();
+    let remove_bound_objects = bound_objects.clone();
+    let registry = Rc::new(core.get_registry()?);
+    let registry_weak = Rc::downgrade(&registry);
+    let remove_consumer = consumer.clone();
     let listener = registry
         .add_listener_local()
-        .global(move |global| consumer.process_pipewire_update(global))
+        .global(move |global| {
+            global_factories.borrow_mut().process_global(global);
+            consumer.process_pipewire_update(global);
+            if let Some(registry) = registry_weak.upgrade() {
+                match bind_object(&registry, &consumer, global) {
+                    Ok(Some(bound_object)) => {
+                        global_bound_objects
+                            .borrow_mut()
+                            .insert(global.id, bound_object);
+                    }
+                    Ok(None) => {}
+                    Err(error) => consumer.process_bind_error(global.id, error),
+                }
+            }
+        })
+        .global_remove(move |global_id| {
+            remove_factories.borrow_mut().remove(global_id);
+            remove_bound_objects.borrow_mut().remove(&global_id);
+            remove_consumer.process_pipewire_remove(global_id);
+        })
         .register();
 
-    let factories = get_factory_names(&main_loop, &registry).unwrap();
-    let pipewire_factory = PipewireFactory { factories, core };
+    // Everything announced before the reply to this sync is the initial
+    // state of the graph, including the factories. The done listener quits
+    // the loop once it arrives, so requests are only served afterwards.
+    initial_sync.set(Some(core.sync(0)?));
+    main_loop.run();
+
+    if disconnect_reason.borrow().is_none() {
+        for object_type in &REQUIRED_FACTORIES {
+            if let Err(error) = factories.borrow().require(object_type) {
+                logger.log_info(&format!("{error:#}"));
+            }
+        }
+    }
 
-    let _receiver = pipewire_factory_request_receiver.attach(
+    let pipewire_factory = PipewireFactory {
+        factories,
+        core,
+        registry,
+    };
+
+    let receiver = pipewire_factory_request_receiver.attach(
         main_loop.loop_(),
         move |command: PipewireFactoryRequest| {
             pipewire_factory.process_command(command);
         },
     );
-    main_loop.run();
+    if disconnect_reason.borrow().is_none() {
+        main_loop.run();
+    }
 
+    // The listeners have to go before the proxies they are registered on,
+    // which are owned by the factory inside the attached receiver.
     drop(listener);
+    bound_objects.borrow_mut().clear();
+    drop(core_listener);
+    let receiver = receiver.deattach();
 
-    Ok(())
-}
+    if let Some(reason) = disconnect_reason.borrow().as_ref() {
+        logger.log_info(&format!("PipeWire core error: {reason}"));
+    }
 
-#[derive(Debug)]
-#[allow(dead_code)]
-pub struct Factories {
-    pub link: String,
+    Ok(receiver)
 }
 
-fn get_factory_names(main_loop: &MainLoop, registry: &Registry) -> Result<Factories> {
-    print!("Getting factory names");
-    let factory: Rc<OnceCell<String>> = Rc::new(OnceCell::new());
-    let factory_clone = factory.clone();
-    let main_loop_weak = main_loop.downgrade();
-    let listener = registry
-        .add_listener_local()
-        .global(move |global| {
-            if let Some(main_loop) = main_loop_weak.upgrade() {
-                main_loop.quit();
+/// Binds the globals whose info events carry state the registry tracks.
+fn bind_object(
+    registry: &Registry,
+    consumer: &Rc<PipewireEventConsumer>,
+    global: &GlobalObject<&DictRef>,
+) -> Result<Option<BoundObject>> {
+    let global_id = global.id;
+    let consumer = consumer.clone();
+    let bound_object = match global.type_ {
+        ObjectType::Node => {
+            let proxy: Node = registry.bind(global)?;
+            let listener = proxy
+                .add_listener_local()
+                .info(move |info| consumer.process_node_info(global_id, info))
+                .register();
+            BoundObject::Node {
+                _proxy: proxy,
+                _listener: listener,
             }
-            if let Some(props) = global.props {
-                if props.get("factory.type.name") == Some(ObjectType::Link.to_str()) {
-                    let factory_name = props.get("factory.name").expect("Factory has no name");
-                    let _ = factory_clone.set(factory_name.to_owned());
-                }
-
-                if factory_clone.get().is_some() {}
+        }
+        ObjectType::Link => {
+            let proxy: Link = registry.bind(global)?;
+            let listener = proxy
+                .add_listener_local()
+                .info(move |info| consumer.process_link_info(global_id, info))
+                .register();
+            BoundObject::Link {
+                _proxy: proxy,
+                _listener: listener,
             }
-        })
-        .register();
-
-    main_loop.run();
+        }
+        ObjectType::Device => {
+            let proxy: Device = registry.bind(global)?;
+            let listener = proxy
+                .add_listener_local()
+                .info(move |info| consumer.process_object_info(global_id, info.props()))
+                .register();
+            BoundObject::Device {
+                _proxy: proxy,
+                _listener: listener,
+            }
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(bound_object))
+}
 
-    drop(listener);
+fn connect(logger: &Logger, context: &Context, config: &ServerConfig, retry: bool) -> Result<Core> {
+    let mut retry_interval = Duration::from_millis(config.startup.retry_interval_ms);
+    loop {
+        let properties = config.pipewire_remote.as_ref().map(|remote| {
+            pipewire::properties::properties! {
+                *pipewire::keys::REMOTE_NAME => remote.as_str()
+            }
+        });
 
-    Ok(Factories {
-        link: factory.get().unwrap().clone(),
-    })
+        match context.connect(properties) {
+            Ok(core) => return Ok(core),
+            Err(error) if retry => {
+                logger.log_info(&format!(
+                    "Waiting for PipeWire: {error}, retrying in {}ms",
+                    retry_interval.as_millis()
+                ));
+                thread::sleep(retry_interval);
+                retry_interval = (retry_interval * 2).min(MAX_RECONNECT_INTERVAL);
+            }
+            Err(error) => return Err(error.into()),
+        }
+    }
 }
@@ -1,61 +1,249 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::time::Duration;
 
+use anyhow::anyhow;
 use tokio::select;
-use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::sync::mpsc::{Receiver, Sender, UnboundedReceiver, UnboundedSender};
 
 use crate::pipewire_event_consumer::PipewireUpdateEvent;
+use crate::pipewire_factory::PipewireFactoryRequest;
+use crate::port_pairing::pair_ports;
 
 mod entities;
+mod events;
+mod graph;
+mod index;
+mod list_filter;
+mod patchbay;
 
 pub use crate::pipewire_registry::entities::{
-    Application, Device, Link, Node, Port, PortDirection,
+    Application, ConnectionState, Core, Device, Factory, Link, LinkState, Metadata, Module, Node,
+    NodeState, Port, PortDirection, Properties, RegistryStatus,
 };
+pub use crate::pipewire_registry::events::{RegistryEntity, RegistryEvent, RegistrySnapshot};
+use crate::pipewire_registry::graph::GraphNode;
+pub use crate::pipewire_registry::graph::RegistryGraph;
+use crate::pipewire_registry::index::Index;
+use crate::pipewire_registry::list_filter::matches;
+pub use crate::pipewire_registry::list_filter::{ListFilter, NamePattern};
+pub use crate::pipewire_registry::patchbay::{
+    DesiredLink, DesiredLinkState, DesiredLinkStatus, NodeLink, Patchbay,
+};
+
+/// The ways a single object can be addressed by clients.
+#[derive(Debug, Clone)]
+pub enum Lookup {
+    GlobalId(u32),
+    ObjectSerial(u64),
+    Name(String),
+}
+
+impl fmt::Display for Lookup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Lookup::GlobalId(global_id) => write!(f, "global id {global_id}"),
+            Lookup::ObjectSerial(object_serial) => write!(f, "object serial {object_serial}"),
+            Lookup::Name(name) => write!(f, "name {name:?}"),
+        }
+    }
+}
+
+/// How many events a watcher may fall behind before it is dropped. A watcher
+/// that does not keep up would otherwise grow the registry's memory without
+/// bound.
+const SUBSCRIBER_CAPACITY: usize = 1024;
 
 pub enum PipewireRegistryRequests {
     GetDevicesList {
+        filter: ListFilter,
         reply_sender: tokio::sync::oneshot::Sender<Vec<Device>>,
     },
     GetNodesList {
+        filter: ListFilter,
         reply_sender: tokio::sync::oneshot::Sender<Vec<Node>>,
     },
     ListPorts {
+        filter: ListFilter,
         reply_sender: tokio::sync::oneshot::Sender<Vec<Port>>,
     },
     GetApplicationsList {
+        filter: ListFilter,
         reply_sender: tokio::sync::oneshot::Sender<Vec<Application>>,
     },
     GetPortByObjectSerial {
-        object_serial: u16,
+        object_serial: u64,
         reply_sender: tokio::sync::oneshot::Sender<Option<Port>>,
     },
+    ResolvePort {
+        name: String,
+        direction: PortDirection,
+        reply_sender: tokio::sync::oneshot::Sender<Vec<Port>>,
+    },
+    GetNode {
+        lookup: Lookup,
+        reply_sender: tokio::sync::oneshot::Sender<Vec<Node>>,
+    },
+    GetDevice {
+        lookup: Lookup,
+        reply_sender: tokio::sync::oneshot::Sender<Vec<Device>>,
+    },
+    GetLink {
+        lookup: Lookup,
+        reply_sender: tokio::sync::oneshot::Sender<Vec<Link>>,
+    },
+    GetApplication {
+        lookup: Lookup,
+        reply_sender: tokio::sync::oneshot::Sender<Vec<Application>>,
+    },
     ListLinks {
+        filter: ListFilter,
         reply_sender: tokio::sync::oneshot::Sender<Vec<Link>>,
     },
+    ListModules {
+        filter: ListFilter,
+        reply_sender: tokio::sync::oneshot::Sender<Vec<Module>>,
+    },
+    ListFactories {
+        filter: ListFilter,
+        reply_sender: tokio::sync::oneshot::Sender<Vec<Factory>>,
+    },
+    ListMetadata {
+        filter: ListFilter,
+        reply_sender: tokio::sync::oneshot::Sender<Vec<Metadata>>,
+    },
+    ListCores {
+        filter: ListFilter,
+        reply_sender: tokio::sync::oneshot::Sender<Vec<Core>>,
+    },
+    GetObjectProperties {
+        global_id: u32,
+        reply_sender: tokio::sync::oneshot::Sender<Option<Properties>>,
+    },
+    WatchRegistry {
+        reply_sender: tokio::sync::oneshot::Sender<Receiver<RegistryEvent>>,
+    },
+    /// Replies with the link between the two ports if it exists, otherwise
+    /// with a receiver that gets the link once PipeWire announces it.
+    WaitForLink {
+        output_port_id: u32,
+        input_port_id: u32,
+        reply_sender:
+            tokio::sync::oneshot::Sender<Result<Link, tokio::sync::oneshot::Receiver<Link>>>,
+    },
+    GetStatus {
+        reply_sender: tokio::sync::oneshot::Sender<RegistryStatus>,
+    },
+    AddDesiredLink {
+        link: DesiredLink,
+        reply_sender: tokio::sync::oneshot::Sender<anyhow::Result<DesiredLinkStatus>>,
+    },
+    RemoveDesiredLink {
+        link: DesiredLink,
+        reply_sender: tokio::sync::oneshot::Sender<anyhow::Result<bool>>,
+    },
+    ListDesiredLinks {
+        reply_sender: tokio::sync::oneshot::Sender<Vec<DesiredLinkStatus>>,
+    },
+    ListLinkNames {
+        reply_sender: tokio::sync::oneshot::Sender<Vec<(Link, DesiredLink)>>,
+    },
+    GetGraph {
+        reply_sender: tokio::sync::oneshot::Sender<RegistryGraph>,
+    },
+}
+
+impl PipewireRegistryRequests {
+    /// Queries about the graph are held back until the initial sync is done,
+    /// so they never see a half filled registry, and dropped while PipeWire
+    /// is not connected.
+    pub fn requires_ready(&self) -> bool {
+        !matches!(
+            self,
+            PipewireRegistryRequests::WatchRegistry { .. }
+                | PipewireRegistryRequests::GetStatus { .. }
+        )
+    }
 }
 
+struct LinkWaiter {
+    output_port_id: u32,
+    input_port_id: u32,
+    link_sender: tokio::sync::oneshot::Sender<Link>,
+}
+
+/// How long a link the factory created may take to show up in the registry
+/// before it is created again.
+const DESIRED_LINK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The outcome of a link the registry asked the factory to create for the
+/// patchbay, along with the number of the attempt. Success is only reported
+/// once the link had time to show up in the registry.
+type PatchbayResult = (DesiredLink, u64, anyhow::Result<()>);
+
 pub struct PipewireRegistry {
     pipewire_event_receiver: UnboundedReceiver<PipewireUpdateEvent>,
     pipewire_registry_request_receiver: UnboundedReceiver<PipewireRegistryRequests>,
-    ports: BTreeMap<(PortDirection, u16, u16), Port>,
-    nodes: Vec<Node>,
-    applications: Vec<Application>,
-    devices: Vec<Device>,
-    links: Vec<Link>,
+    pipewire_factory_request_sender: pipewire::channel::Sender<PipewireFactoryRequest>,
+    patchbay: Patchbay,
+    patchbay_result_sender: UnboundedSender<PatchbayResult>,
+    patchbay_result_receiver: UnboundedReceiver<PatchbayResult>,
+    ports: BTreeMap<u32, Port>,
+    nodes: BTreeMap<u32, Node>,
+    applications: BTreeMap<u32, Application>,
+    devices: BTreeMap<u32, Device>,
+    links: BTreeMap<u32, Link>,
+    modules: BTreeMap<u32, Module>,
+    factories: BTreeMap<u32, Factory>,
+    metadata: BTreeMap<u32, Metadata>,
+    cores: BTreeMap<u32, Core>,
+    properties: BTreeMap<u32, Properties>,
+    object_serials: Index<u64>,
+    node_names: Index<String>,
+    device_names: Index<String>,
+    application_names: Index<String>,
+    subscribers: Vec<Sender<RegistryEvent>>,
+    link_waiters: Vec<LinkWaiter>,
+    connection_state: ConnectionState,
+    ready: bool,
+    pending_requests: Vec<PipewireRegistryRequests>,
 }
 
 impl PipewireRegistry {
     pub fn new(
         pipewire_event_receiver: UnboundedReceiver<PipewireUpdateEvent>,
         pipewire_registry_request_receiver: UnboundedReceiver<PipewireRegistryRequests>,
+        pipewire_factory_request_sender: pipewire::channel::Sender<PipewireFactoryRequest>,
+        patchbay: Patchbay,
     ) -> Self {
+        let (patchbay_result_sender, patchbay_result_receiver) =
+            tokio::sync::mpsc::unbounded_channel();
         PipewireRegistry {
             pipewire_event_receiver,
             pipewire_registry_request_receiver,
+            pipewire_factory_request_sender,
+            patchbay,
+            patchbay_result_sender,
+            patchbay_result_receiver,
             ports: BTreeMap::new(),
-            nodes: Vec::new(),
-            applications: Vec::new(),
-            devices: Vec::new(),
-            links: Vec::new(),
+            nodes: BTreeMap::new(),
+            applications: BTreeMap::new(),
+            devices: BTreeMap::new(),
+            links: BTreeMap::new(),
+            modules: BTreeMap::new(),
+            factories: BTreeMap::new(),
+            metadata: BTreeMap::new(),
+            cores: BTreeMap::new(),
+            properties: BTreeMap::new(),
+            object_serials: Index::default(),
+            node_names: Index::default(),
+            device_names: Index::default(),
+            application_names: Index::default(),
+            subscribers: Vec::new(),
+            link_waiters: Vec::new(),
+            connection_state: ConnectionState::Disconnected,
+            ready: false,
+            pending_requests: Vec::new(),
         }
     }
 
@@ -67,6 +255,19 @@ impl PipewireRegistry {
                 }
                 pipewire_event = self.pipewire_event_receiver.recv() => {
                     self.process_pipewire_event(pipewire_event.unwrap()).await;
+                    self.enforce_patchbay();
+                }
+                patchbay_result = self.patchbay_result_receiver.recv() => {
+                    match patchbay_result.unwrap() {
+                        (link, attempt, Ok(())) => {
+                            if self.patchbay.creation_expired(&link, attempt) {
+                                self.enforce_patchbay();
+                            }
+                        }
+                        (link, attempt, Err(error)) => {
+                            self.patchbay.creation_failed(&link, attempt, format!("{error:#}"));
+                        }
+                    }
                 }
             };
         }
@@ -75,6 +276,7 @@ impl PipewireRegistry {
     async fn process_pipewire_event(&mut self, event: PipewireUpdateEvent) {
         match event {
             PipewireUpdateEvent::Link {
+                global_id,
                 object_serial,
                 factory_id,
                 client_id,
@@ -82,16 +284,34 @@ impl PipewireRegistry {
                 input_port_id,
                 output_node_id,
                 input_node_id,
-            } => self.links.push(Link {
-                object_serial: object_serial.parse().unwrap_or(u16::MAX),
-                factory_id: factory_id.parse().unwrap_or(u16::MAX),
-                client_id: client_id.parse().unwrap_or(u16::MAX),
-                output_port_id: output_port_id.parse().unwrap_or(u16::MAX),
-                input_port_id: input_port_id.parse().unwrap_or(u16::MAX),
-                output_node_id: output_node_id.parse().unwrap_or(u16::MAX),
-                input_node_id: input_node_id.parse().unwrap_or(u16::MAX),
-            }),
+            } => {
+                // The state and format come from the link's info, keep them
+                // when the global is announced again.
+                let (state, format) = match self.links.get(&global_id) {
+                    Some(link) => (link.state.clone(), link.format.clone()),
+                    None => (LinkState::Unknown, None),
+                };
+                let link = Link {
+                    global_id,
+                    object_serial,
+                    factory_id,
+                    client_id,
+                    output_port_id,
+                    input_port_id,
+                    output_node_id,
+                    input_node_id,
+                    state,
+                    format,
+                };
+                let previous = self.links.insert(global_id, link.clone());
+                self.answer_link_waiters(&link);
+                self.publish_upsert(
+                    previous.map(RegistryEntity::Link),
+                    RegistryEntity::Link(link),
+                );
+            }
             PipewireUpdateEvent::Node {
+                global_id,
                 object_serial,
                 factory_id,
                 client_id,
@@ -99,16 +319,34 @@ impl PipewireRegistry {
                 application_name,
                 node_name,
                 media_class,
-            } => self.nodes.push(Node {
-                object_serial: object_serial.parse().unwrap_or(u16::MAX),
-                factory_id: factory_id.parse().unwrap_or(u16::MAX),
-                client_id: client_id.parse().unwrap_or(u16::MAX),
-                client_api,
-                application_name,
-                node_name,
-                media_class,
-            }),
+            } => {
+                // The state and port counts come from the node's info, keep
+                // them when the global is announced again.
+                let (state, input_ports, output_ports) = match self.nodes.get(&global_id) {
+                    Some(node) => (node.state.clone(), node.input_ports, node.output_ports),
+                    None => (NodeState::Unknown, 0, 0),
+                };
+                let node = Node {
+                    global_id,
+                    object_serial,
+                    factory_id,
+                    client_id,
+                    client_api,
+                    application_name,
+                    node_name,
+                    media_class,
+                    state,
+                    input_ports,
+                    output_ports,
+                };
+                let previous = self.nodes.insert(global_id, node.clone());
+                self.publish_upsert(
+                    previous.map(RegistryEntity::Node),
+                    RegistryEntity::Node(node),
+                );
+            }
             PipewireUpdateEvent::Device {
+                global_id,
                 name,
                 factory_id,
                 client_id,
@@ -116,16 +354,25 @@ impl PipewireRegistry {
                 nick,
                 media_class,
                 object_serial,
-            } => self.devices.push(Device {
-                name,
-                factory_id: factory_id.parse().unwrap_or(u16::MAX),
-                client_id: client_id.parse().unwrap_or(u16::MAX),
-                description,
-                nick,
-                media_class,
-                object_serial: object_serial.parse().unwrap_or(u16::MAX),
-            }),
+            } => {
+                let device = Device {
+                    global_id,
+                    name,
+                    factory_id,
+                    client_id,
+                    description,
+                    nick,
+                    media_class,
+                    object_serial,
+                };
+                let previous = self.devices.insert(global_id, device.clone());
+                self.publish_upsert(
+                    previous.map(RegistryEntity::Device),
+                    RegistryEntity::Device(device),
+                );
+            }
             PipewireUpdateEvent::Port {
+                global_id,
                 id,
                 name,
                 direction,
@@ -139,8 +386,9 @@ impl PipewireRegistry {
                 object_serial,
             } => {
                 let port = Port {
-                    id: id.parse().unwrap_or(u16::MAX),
-                    node_id: node_id.parse().unwrap_or(u16::MAX),
+                    global_id,
+                    id,
+                    node_id,
                     name,
                     direction: match direction.as_str() {
                         "in" => PortDirection::In,
@@ -153,12 +401,16 @@ impl PipewireRegistry {
                     path,
                     dsp_format,
                     audio_channel,
-                    object_serial: object_serial.parse().unwrap_or(u16::MAX),
+                    object_serial,
                 };
-                self.ports
-                    .insert((port.direction, port.node_id, port.id), port);
+                let previous = self.ports.insert(global_id, port.clone());
+                self.publish_upsert(
+                    previous.map(RegistryEntity::Port),
+                    RegistryEntity::Port(port),
+                );
             }
             PipewireUpdateEvent::Application {
+                global_id,
                 object_serial,
                 module_id,
                 pipewire_protocol,
@@ -168,50 +420,1375 @@ impl PipewireRegistry {
                 pipewire_sec_socket,
                 pipewire_access,
                 name,
-            } => self.applications.push(Application {
-                object_serial: object_serial.parse().unwrap_or(u16::MAX),
-                module_id: module_id.parse().unwrap_or(u16::MAX),
-                pipewire_protocol,
-                pipewire_sec_pid,
-                pipewire_sec_uid,
-                pipewire_sec_gid,
-                pipewire_sec_socket,
-                pipewire_access,
+            } => {
+                let application = Application {
+                    global_id,
+                    object_serial,
+                    module_id,
+                    pipewire_protocol,
+                    pipewire_sec_pid,
+                    pipewire_sec_uid,
+                    pipewire_sec_gid,
+                    pipewire_sec_socket,
+                    pipewire_access,
+                    name,
+                };
+                let previous = self.applications.insert(global_id, application.clone());
+                self.publish_upsert(
+                    previous.map(RegistryEntity::Application),
+                    RegistryEntity::Application(application),
+                );
+            }
+            PipewireUpdateEvent::Module {
+                global_id,
+                object_serial,
+                name,
+            } => {
+                let module = Module {
+                    global_id,
+                    object_serial,
+                    name,
+                };
+                let previous = self.modules.insert(global_id, module.clone());
+                self.publish_upsert(
+                    previous.map(RegistryEntity::Module),
+                    RegistryEntity::Module(module),
+                );
+            }
+            PipewireUpdateEvent::Factory {
+                global_id,
+                object_serial,
+                module_id,
                 name,
-            }),
+                type_name,
+                type_version,
+            } => {
+                let factory = Factory {
+                    global_id,
+                    object_serial,
+                    module_id,
+                    name,
+                    type_name,
+                    type_version,
+                };
+                let previous = self.factories.insert(global_id, factory.clone());
+                self.publish_upsert(
+                    previous.map(RegistryEntity::Factory),
+                    RegistryEntity::Factory(factory),
+                );
+            }
+            PipewireUpdateEvent::Metadata {
+                global_id,
+                object_serial,
+                name,
+            } => {
+                let metadata = Metadata {
+                    global_id,
+                    object_serial,
+                    name,
+                };
+                let previous = self.metadata.insert(global_id, metadata.clone());
+                self.publish_upsert(
+                    previous.map(RegistryEntity::Metadata),
+                    RegistryEntity::Metadata(metadata),
+                );
+            }
+            PipewireUpdateEvent::Core {
+                global_id,
+                object_serial,
+                name,
+            } => {
+                let core = Core {
+                    global_id,
+                    object_serial,
+                    name,
+                };
+                let previous = self.cores.insert(global_id, core.clone());
+                self.publish_upsert(
+                    previous.map(RegistryEntity::Core),
+                    RegistryEntity::Core(core),
+                );
+            }
+            PipewireUpdateEvent::Properties {
+                global_id,
+                properties,
+            } => {
+                self.properties.insert(global_id, properties);
+            }
+            PipewireUpdateEvent::NodeInfo {
+                global_id,
+                state,
+                input_ports,
+                output_ports,
+                properties,
+            } => {
+                if let Some(properties) = properties {
+                    self.update_properties(global_id, properties);
+                }
+                if let Some(node) = self.nodes.get_mut(&global_id) {
+                    if node.state != state
+                        || node.input_ports != input_ports
+                        || node.output_ports != output_ports
+                    {
+                        node.state = state;
+                        node.input_ports = input_ports;
+                        node.output_ports = output_ports;
+                        let node = node.clone();
+                        self.publish(RegistryEvent::Changed(RegistryEntity::Node(node)));
+                    }
+                }
+            }
+            PipewireUpdateEvent::LinkInfo {
+                global_id,
+                state,
+                format,
+                properties,
+            } => {
+                if let Some(properties) = properties {
+                    self.update_properties(global_id, properties);
+                }
+                if let Some(link) = self.links.get_mut(&global_id) {
+                    // The format is only part of the info while it changes,
+                    // keep the last negotiated one otherwise.
+                    let format = format.or_else(|| link.format.clone());
+                    if link.state != state || link.format != format {
+                        link.state = state;
+                        link.format = format;
+                        let link = link.clone();
+                        self.publish(RegistryEvent::Changed(RegistryEntity::Link(link)));
+                    }
+                }
+            }
+            PipewireUpdateEvent::ObjectInfo {
+                global_id,
+                properties,
+            } => self.update_properties(global_id, properties),
+            PipewireUpdateEvent::GlobalRemoved { global_id } => self.remove_global(global_id),
+            PipewireUpdateEvent::Connected => {
+                self.set_status(ConnectionState::Connected, false);
+            }
+            PipewireUpdateEvent::Synced => {
+                self.set_status(ConnectionState::Connected, true);
+                for request in std::mem::take(&mut self.pending_requests) {
+                    self.process_registry_request(request).await;
+                }
+            }
+            PipewireUpdateEvent::Disconnected => {
+                // Dropping the held back requests and link waiters tells
+                // their callers that PipeWire is gone.
+                self.pending_requests.clear();
+                self.link_waiters.clear();
+                self.clear();
+                self.patchbay.reset();
+                self.set_status(ConnectionState::Disconnected, false);
+            }
+        }
+    }
+
+    /// Replaces the properties of a known object with the ones reported by
+    /// its proxy and tells subscribers when they differ.
+    fn update_properties(&mut self, global_id: u32, properties: Properties) {
+        let Some(current) = self.properties.get_mut(&global_id) else {
+            return;
+        };
+        if *current != properties {
+            *current = properties.clone();
+            self.publish(RegistryEvent::PropertiesChanged {
+                global_id,
+                properties,
+            });
+        }
+    }
+
+    fn remove_global(&mut self, global_id: u32) {
+        let mut removed = Vec::new();
+
+        if let Some(node) = self.nodes.remove(&global_id) {
+            removed.push(RegistryEntity::Node(node));
+        }
+        if let Some(device) = self.devices.remove(&global_id) {
+            removed.push(RegistryEntity::Device(device));
+        }
+        if let Some(application) = self.applications.remove(&global_id) {
+            removed.push(RegistryEntity::Application(application));
+        }
+        if let Some(module) = self.modules.remove(&global_id) {
+            removed.push(RegistryEntity::Module(module));
+        }
+        if let Some(factory) = self.factories.remove(&global_id) {
+            removed.push(RegistryEntity::Factory(factory));
+        }
+        if let Some(metadata) = self.metadata.remove(&global_id) {
+            removed.push(RegistryEntity::Metadata(metadata));
+        }
+        if let Some(core) = self.cores.remove(&global_id) {
+            removed.push(RegistryEntity::Core(core));
+        }
+
+        let removed_ports: Vec<u32> = self
+            .ports
+            .iter()
+            .filter(|(id, p)| **id == global_id || p.node_id == global_id)
+            .map(|(id, _)| *id)
+            .collect();
+        for port_id in removed_ports.iter() {
+            if let Some(port) = self.ports.remove(port_id) {
+                removed.push(RegistryEntity::Port(port));
+            }
+        }
+
+        let removed_links: Vec<u32> = self
+            .links
+            .iter()
+            .filter(|(id, l)| {
+                **id == global_id
+                    || l.output_node_id == global_id
+                    || l.input_node_id == global_id
+                    || removed_ports.contains(&l.output_port_id)
+                    || removed_ports.contains(&l.input_port_id)
+            })
+            .map(|(id, _)| *id)
+            .collect();
+        for link_id in removed_links.iter() {
+            if let Some(link) = self.links.remove(link_id) {
+                removed.push(RegistryEntity::Link(link));
+            }
+        }
+
+        self.properties.remove(&global_id);
+        for entity in removed {
+            self.properties.remove(&entity.global_id());
+            self.unindex(&entity);
+            self.publish(RegistryEvent::Removed(entity));
+        }
+    }
+
+    /// Drops every known object, the globals are announced again after the
+    /// next successful connection.
+    fn clear(&mut self) {
+        let mut removed = Vec::new();
+        removed.extend(
+            std::mem::take(&mut self.links)
+                .into_values()
+                .map(RegistryEntity::Link),
+        );
+        removed.extend(
+            std::mem::take(&mut self.ports)
+                .into_values()
+                .map(RegistryEntity::Port),
+        );
+        removed.extend(
+            std::mem::take(&mut self.nodes)
+                .into_values()
+                .map(RegistryEntity::Node),
+        );
+        removed.extend(
+            std::mem::take(&mut self.devices)
+                .into_values()
+                .map(RegistryEntity::Device),
+        );
+        removed.extend(
+            std::mem::take(&mut self.applications)
+                .into_values()
+                .map(RegistryEntity::Application),
+        );
+        removed.extend(
+            std::mem::take(&mut self.metadata)
+                .into_values()
+                .map(RegistryEntity::Metadata),
+        );
+        removed.extend(
+            std::mem::take(&mut self.factories)
+                .into_values()
+                .map(RegistryEntity::Factory),
+        );
+        removed.extend(
+            std::mem::take(&mut self.modules)
+                .into_values()
+                .map(RegistryEntity::Module),
+        );
+        removed.extend(
+            std::mem::take(&mut self.cores)
+                .into_values()
+                .map(RegistryEntity::Core),
+        );
+        self.properties.clear();
+        self.object_serials.clear();
+        self.node_names.clear();
+        self.device_names.clear();
+        self.application_names.clear();
+
+        for entity in removed {
+            self.publish(RegistryEvent::Removed(entity));
+        }
+    }
+
+    fn set_status(&mut self, connection_state: ConnectionState, ready: bool) {
+        if self.connection_state != connection_state || self.ready != ready {
+            self.connection_state = connection_state;
+            self.ready = ready;
+            self.publish(RegistryEvent::StatusChanged(self.status()));
+        }
+    }
+
+    fn status(&self) -> RegistryStatus {
+        RegistryStatus {
+            connection_state: self.connection_state,
+            ready: self.ready,
+        }
+    }
+
+    fn snapshot(&self) -> RegistrySnapshot {
+        RegistrySnapshot {
+            status: self.status(),
+            nodes: self.nodes.values().cloned().collect(),
+            ports: self.ports.values().cloned().collect(),
+            links: self.links.values().cloned().collect(),
+            devices: self.devices.values().cloned().collect(),
+            applications: self.applications.values().cloned().collect(),
+            modules: self.modules.values().cloned().collect(),
+            factories: self.factories.values().cloned().collect(),
+            metadata: self.metadata.values().cloned().collect(),
+            cores: self.cores.values().cloned().collect(),
+        }
+    }
+
+    fn graph(&self) -> RegistryGraph {
+        let nodes = self
+            .nodes
+            .values()
+            .map(|node| {
+                let mut ports: Vec<Port> = self
+                    .ports
+                    .values()
+                    .filter(|port| port.node_id == node.global_id)
+                    .cloned()
+                    .collect();
+                ports.sort_by_key(|port| (port.direction, port.id));
+                GraphNode {
+                    node: node.clone(),
+                    device_id: self
+                        .node_device_id(node)
+                        .filter(|device_id| self.devices.contains_key(device_id)),
+                    ports,
+                }
+            })
+            .collect();
+
+        let in_graph = |port_id: &u32| {
+            self.ports
+                .get(port_id)
+                .is_some_and(|port| self.nodes.contains_key(&port.node_id))
+        };
+        RegistryGraph {
+            devices: self.devices.values().cloned().collect(),
+            nodes,
+            links: self
+                .links
+                .values()
+                .filter(|link| in_graph(&link.output_port_id) && in_graph(&link.input_port_id))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    fn publish_upsert(&mut self, previous: Option<RegistryEntity>, entity: RegistryEntity) {
+        let existed = previous.is_some();
+        if let Some(previous) = previous {
+            self.unindex(&previous);
+        }
+        self.index(&entity);
+        if existed {
+            self.publish(RegistryEvent::Changed(entity));
+        } else {
+            self.publish(RegistryEvent::Added(entity));
+        }
+    }
+
+    fn index(&mut self, entity: &RegistryEntity) {
+        let global_id = entity.global_id();
+        self.object_serials
+            .insert(entity.object_serial(), global_id);
+        match entity {
+            RegistryEntity::Node(node) => self.node_names.insert(node.node_name.clone(), global_id),
+            RegistryEntity::Device(device) => {
+                self.device_names.insert(device.name.clone(), global_id)
+            }
+            RegistryEntity::Application(application) => self
+                .application_names
+                .insert(application.name.clone(), global_id),
+            _ => {}
+        }
+    }
+
+    fn unindex(&mut self, entity: &RegistryEntity) {
+        let global_id = entity.global_id();
+        self.object_serials
+            .remove(&entity.object_serial(), global_id);
+        match entity {
+            RegistryEntity::Node(node) => self.node_names.remove(&node.node_name, global_id),
+            RegistryEntity::Device(device) => self.device_names.remove(&device.name, global_id),
+            RegistryEntity::Application(application) => {
+                self.application_names.remove(&application.name, global_id)
+            }
+            _ => {}
+        }
+    }
+
+    /// Whether a port is called `name`, either JACK style as
+    /// `node name:port name` or by its alias.
+    fn port_is_named(&self, port: &Port, name: &str) -> bool {
+        port.alias == name
+            || self.nodes.get(&port.node_id).is_some_and(|node| {
+                name.strip_prefix(node.node_name.as_str())
+                    .and_then(|rest| rest.strip_prefix(':'))
+                    .is_some_and(|port_name| port_name == port.name)
+            })
+    }
+
+    fn node_matches(&self, node: &Node, filter: &ListFilter) -> bool {
+        filter.matches_name(&[&node.node_name]) && self.node_owner_matches(node, filter)
+    }
+
+    /// The node criteria that also select the ports and links of a node.
+    fn node_owner_matches(&self, node: &Node, filter: &ListFilter) -> bool {
+        if let Some(application) = filter.application.as_deref() {
+            if !self.node_application_is(node, application) {
+                return false;
+            }
+        }
+        matches(filter.media_class.as_deref(), node.media_class.as_str())
+            && (filter.device_id.is_none() || self.node_device_id(node) == filter.device_id)
+    }
+
+    fn port_matches(&self, port: &Port, filter: &ListFilter) -> bool {
+        let node_matches = !filter.has_node_criteria()
+            || self
+                .nodes
+                .get(&port.node_id)
+                .is_some_and(|node| self.node_owner_matches(node, filter));
+        node_matches
+            && matches(filter.node_id.as_ref(), &port.node_id)
+            && filter.matches_name(&[&port.name, &port.alias])
+            && matches(filter.direction.as_ref(), &port.direction)
+            && matches(filter.physical.as_ref(), &port.physical)
+            && matches(
+                filter.terminal.as_ref(),
+                &self.flag(port.global_id, "port.terminal"),
+            )
+            && matches(
+                filter.monitor.as_ref(),
+                &self.flag(port.global_id, "port.monitor"),
+            )
+            && matches(filter.dsp_format.as_deref(), port.dsp_format.as_str())
+    }
+
+    /// Links match through the nodes on either end.
+    fn link_matches(&self, link: &Link, filter: &ListFilter) -> bool {
+        let node_matches = (!filter.has_node_criteria() && filter.name.is_none())
+            || [link.output_node_id, link.input_node_id]
+                .iter()
+                .filter_map(|node_id| self.nodes.get(node_id))
+                .any(|node| self.node_matches(node, filter));
+        node_matches
+            && (filter.node_id.is_none()
+                || filter.node_id == Some(link.output_node_id)
+                || filter.node_id == Some(link.input_node_id))
+            && (!filter.unhealthy_only || link.state.is_unhealthy())
+    }
+
+    fn device_matches(&self, device: &Device, filter: &ListFilter) -> bool {
+        matches(filter.media_class.as_deref(), device.media_class.as_str())
+            && filter.matches_name(&[&device.name])
+            && matches(filter.device_id.as_ref(), &device.global_id)
+    }
+
+    fn application_matches(&self, application: &Application, filter: &ListFilter) -> bool {
+        filter.matches_name(&[&application.name])
+            && matches(filter.application.as_deref(), application.name.as_str())
+    }
+
+    /// Nodes carry the application name themselves when the client set it,
+    /// otherwise the owning client is asked.
+    fn node_application_is(&self, node: &Node, application: &str) -> bool {
+        node.application_name == application
+            || node
+                .client_id
+                .and_then(|client_id| self.applications.get(&client_id))
+                .is_some_and(|client| client.name == application)
+    }
+
+    fn node_device_id(&self, node: &Node) -> Option<u32> {
+        self.properties
+            .get(&node.global_id)
+            .and_then(|properties| properties.get("device.id"))
+            .and_then(|device_id| device_id.parse().ok())
+    }
+
+    fn flag(&self, global_id: u32, key: &str) -> bool {
+        self.properties
+            .get(&global_id)
+            .and_then(|properties| properties.get(key))
+            .is_some_and(|value| value == "true")
+    }
+
+    /// Resolves a lookup to the global ids it could refer to. Names are only
+    /// meaningful for object types that have a name index.
+    fn lookup(&self, lookup: &Lookup, names: Option<&Index<String>>) -> Vec<u32> {
+        match lookup {
+            Lookup::GlobalId(global_id) => vec![*global_id],
+            Lookup::ObjectSerial(object_serial) => self.object_serials.get(object_serial),
+            Lookup::Name(name) => names.map(|names| names.get(name)).unwrap_or_default(),
+        }
+    }
+
+    /// Finds the one port of a desired link end. No port means the node or
+    /// port is not there yet, more than one can not be linked reliably.
+    fn resolve_desired_port(
+        &self,
+        node_name: &str,
+        port_name: &str,
+        direction: PortDirection,
+    ) -> Result<Option<&Port>, String> {
+        let node_ids = self.node_names.get(node_name);
+        let ports: Vec<&Port> = self
+            .ports
+            .values()
+            .filter(|port| {
+                port.direction == direction
+                    && port.name == port_name
+                    && node_ids.contains(&port.node_id)
+            })
+            .collect();
+        match ports.as_slice() {
+            [] => Ok(None),
+            [port] => Ok(Some(port)),
+            _ => Err(format!(
+                "{node_name}:{port_name} matches {} ports",
+                ports.len()
+            )),
+        }
+    }
+
+    fn resolve_desired_link(&self, link: &DesiredLink) -> Result<Option<(&Port, &Port)>, String> {
+        let output_port =
+            self.resolve_desired_port(&link.output_node, &link.output_port, PortDirection::Out)?;
+        let input_port =
+            self.resolve_desired_port(&link.input_node, &link.input_port, PortDirection::In)?;
+        Ok(output_port.zip(input_port))
+    }
+
+    /// Describes a link by the names of its nodes and ports, links between
+    /// objects that are not known (yet) have no names.
+    fn link_names(&self, link: &Link) -> Option<DesiredLink> {
+        let output_port = self.ports.get(&link.output_port_id)?;
+        let input_port = self.ports.get(&link.input_port_id)?;
+        let output_node = self.nodes.get(&output_port.node_id)?;
+        let input_node = self.nodes.get(&input_port.node_id)?;
+        Some(DesiredLink {
+            output_node: output_node.node_name.clone(),
+            output_port: output_port.name.clone(),
+            input_node: input_node.node_name.clone(),
+            input_port: input_port.name.clone(),
+        })
+    }
+
+    fn find_link(&self, output_port: &Port, input_port: &Port) -> Option<&Link> {
+        self.links.values().find(|link| {
+            link.output_port_id == output_port.global_id
+                && link.input_port_id == input_port.global_id
+        })
+    }
+
+    fn desired_link_status(&self, link: &DesiredLink) -> DesiredLinkStatus {
+        let state = match self.resolve_desired_link(link) {
+            Err(reason) => DesiredLinkState::Broken { reason },
+            Ok(None) => DesiredLinkState::Pending,
+            Ok(Some((output_port, input_port))) => match self.find_link(output_port, input_port) {
+                Some(Link {
+                    global_id,
+                    state: LinkState::Error(error),
+                    ..
+                }) => DesiredLinkState::Broken {
+                    reason: format!("Link {global_id} failed: {error}"),
+                },
+                Some(existing) => DesiredLinkState::Satisfied {
+                    link_id: existing.global_id,
+                },
+                None => match self.patchbay.failure(link) {
+                    Some(reason) => DesiredLinkState::Broken {
+                        reason: reason.to_string(),
+                    },
+                    None => DesiredLinkState::Pending,
+                },
+            },
+        };
+        DesiredLinkStatus {
+            link: link.clone(),
+            state,
+        }
+    }
+
+    /// Creates the desired links whose ports are all there. A failed attempt
+    /// is not repeated until one of the ports goes away and comes back.
+    fn enforce_patchbay(&mut self) {
+        if !self.ready {
+            return;
+        }
+        self.resolve_layout();
+
+        let mut settled = Vec::new();
+        let mut missing = Vec::new();
+        for link in self.patchbay.links() {
+            match self.resolve_desired_link(link) {
+                Ok(Some((output_port, input_port))) => {
+                    if self.find_link(output_port, input_port).is_some() {
+                        settled.push(link.clone());
+                    } else if !self.patchbay.is_creating(link)
+                        && self.patchbay.failure(link).is_none()
+                    {
+                        missing.push((link.clone(), output_port.clone(), input_port.clone()));
+                    }
+                }
+                _ => settled.push(link.clone()),
+            }
+        }
+
+        for link in settled {
+            self.patchbay.settled(&link);
+        }
+        for (link, output_port, input_port) in missing {
+            self.create_desired_link(link, output_port, input_port);
+        }
+    }
+
+    /// Turns the node links of the layout into port links by pairing the
+    /// ports of the nodes. A name matching several nodes is left out, like an
+    /// ambiguous port of a desired link, and so is a node that has not
+    /// announced all the ports its info counts yet.
+    fn resolve_layout(&mut self) {
+        let single_node = |node_name: &str| match self.node_names.get(node_name).as_slice() {
+            [node_id] => Some(*node_id),
+            _ => None,
+        };
+        let node_ports = |node_id: u32, direction: PortDirection| -> Option<Vec<Port>> {
+            let node = self.nodes.get(&node_id)?;
+            let expected = match direction {
+                PortDirection::In => node.input_ports,
+                PortDirection::Out => node.output_ports,
+                PortDirection::Unknown => return None,
+            };
+            let ports: Vec<Port> = self
+                .ports
+                .values()
+                .filter(|port| port.node_id == node_id && port.direction == direction)
+                .cloned()
+                .collect();
+            (ports.len() == expected as usize).then_some(ports)
+        };
+
+        let mut layout_links = BTreeSet::new();
+        for node_link in self.patchbay.layout() {
+            let (Some(output_node_id), Some(input_node_id)) = (
+                single_node(&node_link.output_node),
+                single_node(&node_link.input_node),
+            ) else {
+                continue;
+            };
+            let (Some(output_ports), Some(input_ports)) = (
+                node_ports(output_node_id, PortDirection::Out),
+                node_ports(input_node_id, PortDirection::In),
+            ) else {
+                continue;
+            };
+            let pairs = pair_ports(output_ports, input_ports);
+            layout_links.extend(
+                pairs
+                    .into_iter()
+                    .map(|(output_port, input_port)| DesiredLink {
+                        output_node: node_link.output_node.clone(),
+                        output_port: output_port.name,
+                        input_node: node_link.input_node.clone(),
+                        input_port: input_port.name,
+                    }),
+            );
+        }
+        self.patchbay.set_layout_links(layout_links);
+    }
+
+    fn create_desired_link(&mut self, link: DesiredLink, output_port: Port, input_port: Port) {
+        let (reply_sender, reply_receiver) = tokio::sync::oneshot::channel();
+        let request = PipewireFactoryRequest::CreateLink {
+            output_port_id: output_port.global_id.to_string(),
+            input_port_id: input_port.global_id.to_string(),
+            output_node_id: output_port.node_id.to_string(),
+            input_node_id: input_port.node_id.to_string(),
+            reply_sender,
+        };
+
+        let attempt = self.patchbay.creation_started(link.clone());
+        if self.pipewire_factory_request_sender.send(request).is_err() {
+            self.patchbay.creation_failed(
+                &link,
+                attempt,
+                String::from("PipeWire is not connected"),
+            );
+            return;
+        }
+
+        let result_sender = self.patchbay_result_sender.clone();
+        tokio::spawn(async move {
+            let result = reply_receiver
+                .await
+                .unwrap_or_else(|_| Err(anyhow!("PipeWire is not connected")));
+            if result.is_ok() {
+                tokio::time::sleep(DESIRED_LINK_TIMEOUT).await;
+            }
+            let _ = result_sender.send((link, attempt, result));
+        });
+    }
+
+    /// Subscribers that are gone or whose queue is full are dropped, which
+    /// ends their event stream once they drained it.
+    fn publish(&mut self, event: RegistryEvent) {
+        self.subscribers
+            .retain(|subscriber| subscriber.try_send(event.clone()).is_ok());
+    }
+
+    fn answer_link_waiters(&mut self, link: &Link) {
+        let (answered, waiting) = std::mem::take(&mut self.link_waiters)
+            .into_iter()
+            .filter(|waiter| !waiter.link_sender.is_closed())
+            .partition(|waiter: &LinkWaiter| {
+                waiter.output_port_id == link.output_port_id
+                    && waiter.input_port_id == link.input_port_id
+            });
+        self.link_waiters = waiting;
+        for waiter in answered {
+            let _ = waiter.link_sender.send(link.clone());
         }
     }
 
     async fn process_registry_request(&mut self, request: PipewireRegistryRequests) {
+        if !self.ready && request.requires_ready() {
+            if self.connection_state == ConnectionState::Connected {
+                self.pending_requests.push(request);
+            }
+            return;
+        }
+
         match request {
-            PipewireRegistryRequests::ListLinks { reply_sender } => {
-                reply_sender.send(self.links.clone()).unwrap();
+            PipewireRegistryRequests::ListLinks {
+                filter,
+                reply_sender,
+            } => {
+                let links = self
+                    .links
+                    .values()
+                    .filter(|link| self.link_matches(link, &filter))
+                    .cloned()
+                    .collect();
+                let _ = reply_sender.send(links);
             }
-            PipewireRegistryRequests::GetDevicesList { reply_sender } => {
-                reply_sender.send(self.devices.clone()).unwrap();
+            PipewireRegistryRequests::ListModules {
+                filter,
+                reply_sender,
+            } => {
+                let modules = self
+                    .modules
+                    .values()
+                    .filter(|module| filter.matches_name(&[&module.name]))
+                    .cloned()
+                    .collect();
+                let _ = reply_sender.send(modules);
             }
-            PipewireRegistryRequests::GetNodesList { reply_sender } => {
-                reply_sender.send(self.nodes.clone()).unwrap();
+            PipewireRegistryRequests::ListFactories {
+                filter,
+                reply_sender,
+            } => {
+                let factories = self
+                    .factories
+                    .values()
+                    .filter(|factory| filter.matches_name(&[&factory.name]))
+                    .cloned()
+                    .collect();
+                let _ = reply_sender.send(factories);
             }
-            PipewireRegistryRequests::ListPorts { reply_sender } => {
-                reply_sender
-                    .send(self.ports.values().cloned().collect())
-                    .unwrap();
+            PipewireRegistryRequests::ListMetadata {
+                filter,
+                reply_sender,
+            } => {
+                let metadata = self
+                    .metadata
+                    .values()
+                    .filter(|metadata| filter.matches_name(&[&metadata.name]))
+                    .cloned()
+                    .collect();
+                let _ = reply_sender.send(metadata);
+            }
+            PipewireRegistryRequests::ListCores {
+                filter,
+                reply_sender,
+            } => {
+                let cores = self
+                    .cores
+                    .values()
+                    .filter(|core| filter.matches_name(&[&core.name]))
+                    .cloned()
+                    .collect();
+                let _ = reply_sender.send(cores);
             }
-            PipewireRegistryRequests::GetApplicationsList { reply_sender } => {
-                reply_sender.send(self.applications.clone()).unwrap();
+            PipewireRegistryRequests::GetObjectProperties {
+                global_id,
+                reply_sender,
+            } => {
+                let _ = reply_sender.send(self.properties.get(&global_id).cloned());
+            }
+            PipewireRegistryRequests::GetDevicesList {
+                filter,
+                reply_sender,
+            } => {
+                let devices = self
+                    .devices
+                    .values()
+                    .filter(|device| self.device_matches(device, &filter))
+                    .cloned()
+                    .collect();
+                let _ = reply_sender.send(devices);
+            }
+            PipewireRegistryRequests::GetNodesList {
+                filter,
+                reply_sender,
+            } => {
+                let nodes = self
+                    .nodes
+                    .values()
+                    .filter(|node| self.node_matches(node, &filter))
+                    .cloned()
+                    .collect();
+                let _ = reply_sender.send(nodes);
+            }
+            PipewireRegistryRequests::ListPorts {
+                filter,
+                reply_sender,
+            } => {
+                let ports = self
+                    .ports
+                    .values()
+                    .filter(|port| self.port_matches(port, &filter))
+                    .cloned()
+                    .collect();
+                let _ = reply_sender.send(ports);
+            }
+            PipewireRegistryRequests::GetApplicationsList {
+                filter,
+                reply_sender,
+            } => {
+                let applications = self
+                    .applications
+                    .values()
+                    .filter(|application| self.application_matches(application, &filter))
+                    .cloned()
+                    .collect();
+                let _ = reply_sender.send(applications);
             }
             PipewireRegistryRequests::GetPortByObjectSerial {
                 object_serial,
                 reply_sender,
             } => {
                 let port = self
+                    .object_serials
+                    .get(&object_serial)
+                    .into_iter()
+                    .find_map(|global_id| self.ports.get(&global_id));
+                let _ = reply_sender.send(port.cloned());
+            }
+            PipewireRegistryRequests::ResolvePort {
+                name,
+                direction,
+                reply_sender,
+            } => {
+                let ports = self
                     .ports
-                    .iter()
-                    .map(|p| p.1)
-                    .find(|p| p.object_serial == object_serial);
-                reply_sender.send(port.cloned()).unwrap();
+                    .values()
+                    .filter(|port| port.direction == direction && self.port_is_named(port, &name))
+                    .cloned()
+                    .collect();
+                let _ = reply_sender.send(ports);
+            }
+            PipewireRegistryRequests::GetNode {
+                lookup,
+                reply_sender,
+            } => {
+                let nodes = self
+                    .lookup(&lookup, Some(&self.node_names))
+                    .into_iter()
+                    .filter_map(|global_id| self.nodes.get(&global_id).cloned())
+                    .collect();
+                let _ = reply_sender.send(nodes);
+            }
+            PipewireRegistryRequests::GetDevice {
+                lookup,
+                reply_sender,
+            } => {
+                let devices = self
+                    .lookup(&lookup, Some(&self.device_names))
+                    .into_iter()
+                    .filter_map(|global_id| self.devices.get(&global_id).cloned())
+                    .collect();
+                let _ = reply_sender.send(devices);
+            }
+            PipewireRegistryRequests::GetLink {
+                lookup,
+                reply_sender,
+            } => {
+                let links = self
+                    .lookup(&lookup, None)
+                    .into_iter()
+                    .filter_map(|global_id| self.links.get(&global_id).cloned())
+                    .collect();
+                let _ = reply_sender.send(links);
+            }
+            PipewireRegistryRequests::GetApplication {
+                lookup,
+                reply_sender,
+            } => {
+                let applications = self
+                    .lookup(&lookup, Some(&self.application_names))
+                    .into_iter()
+                    .filter_map(|global_id| self.applications.get(&global_id).cloned())
+                    .collect();
+                let _ = reply_sender.send(applications);
+            }
+            PipewireRegistryRequests::WatchRegistry { reply_sender } => {
+                let (event_sender, event_receiver) =
+                    tokio::sync::mpsc::channel(SUBSCRIBER_CAPACITY);
+                event_sender
+                    .try_send(RegistryEvent::Snapshot(self.snapshot()))
+                    .unwrap();
+                self.subscribers.push(event_sender);
+                let _ = reply_sender.send(event_receiver);
             }
+            PipewireRegistryRequests::WaitForLink {
+                output_port_id,
+                input_port_id,
+                reply_sender,
+            } => {
+                let link = self.links.values().find(|l| {
+                    l.output_port_id == output_port_id && l.input_port_id == input_port_id
+                });
+                if let Some(link) = link {
+                    let _ = reply_sender.send(Ok(link.clone()));
+                    return;
+                }
+
+                // Waiters whose caller gave up are dropped here and whenever
+                // a link is added.
+                self.link_waiters
+                    .retain(|waiter| !waiter.link_sender.is_closed());
+                let (link_sender, link_receiver) = tokio::sync::oneshot::channel();
+                self.link_waiters.push(LinkWaiter {
+                    output_port_id,
+                    input_port_id,
+                    link_sender,
+                });
+                let _ = reply_sender.send(Err(link_receiver));
+            }
+            PipewireRegistryRequests::GetStatus { reply_sender } => {
+                let _ = reply_sender.send(self.status());
+            }
+            PipewireRegistryRequests::AddDesiredLink { link, reply_sender } => {
+                let result = self.patchbay.add(link.clone()).map(|_| {
+                    self.enforce_patchbay();
+                    self.desired_link_status(&link)
+                });
+                let _ = reply_sender.send(result);
+            }
+            PipewireRegistryRequests::RemoveDesiredLink { link, reply_sender } => {
+                let _ = reply_sender.send(self.patchbay.remove(&link));
+            }
+            PipewireRegistryRequests::ListLinkNames { reply_sender } => {
+                let links = self
+                    .links
+                    .values()
+                    .filter_map(|link| Some((link.clone(), self.link_names(link)?)))
+                    .collect();
+                let _ = reply_sender.send(links);
+            }
+            PipewireRegistryRequests::GetGraph { reply_sender } => {
+                let _ = reply_sender.send(self.graph());
+            }
+            PipewireRegistryRequests::ListDesiredLinks { reply_sender } => {
+                let links = self
+                    .patchbay
+                    .links()
+                    .map(|link| self.desired_link_status(link))
+                    .collect();
+                let _ = reply_sender.send(links);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc::unbounded_channel;
+
+    use super::*;
+
+    fn registry() -> PipewireRegistry {
+        let (_, pipewire_event_receiver) = unbounded_channel();
+        let (_, pipewire_registry_request_receiver) = unbounded_channel();
+        let (pipewire_factory_request_sender, _) = pipewire::channel::channel();
+        PipewireRegistry::new(
+            pipewire_event_receiver,
+            pipewire_registry_request_receiver,
+            pipewire_factory_request_sender,
+            Patchbay::load(None).unwrap(),
+        )
+    }
+
+    fn node(global_id: u32) -> PipewireUpdateEvent {
+        PipewireUpdateEvent::Node {
+            global_id,
+            object_serial: u64::from(global_id),
+            factory_id: None,
+            client_id: None,
+            client_api: String::new(),
+            application_name: String::new(),
+            node_name: format!("node-{global_id}"),
+            media_class: String::new(),
         }
     }
+
+    fn port(global_id: u32, node_id: u32, id: u32, direction: &str) -> PipewireUpdateEvent {
+        PipewireUpdateEvent::Port {
+            global_id,
+            id,
+            name: format!("port-{global_id}"),
+            direction: direction.to_owned(),
+            physical: String::new(),
+            alias: String::new(),
+            group: String::new(),
+            path: String::new(),
+            dsp_format: String::new(),
+            node_id,
+            audio_channel: String::new(),
+            object_serial: u64::from(global_id),
+        }
+    }
+
+    fn link(global_id: u32, output: (u32, u32), input: (u32, u32)) -> PipewireUpdateEvent {
+        PipewireUpdateEvent::Link {
+            global_id,
+            object_serial: u64::from(global_id),
+            factory_id: 0,
+            client_id: None,
+            output_node_id: output.0,
+            output_port_id: output.1,
+            input_node_id: input.0,
+            input_port_id: input.1,
+        }
+    }
+
+    /// A synced registry with two nodes with one output and one input port
+    /// each, the output of the first linked to the input of the second and
+    /// the other way round.
+    async fn linked_nodes() -> PipewireRegistry {
+        let mut registry = registry();
+        for event in [
+            PipewireUpdateEvent::Connected,
+            node(10),
+            port(11, 10, 0, "out"),
+            port(12, 10, 0, "in"),
+            node(20),
+            port(21, 20, 0, "out"),
+            port(22, 20, 0, "in"),
+            link(30, (10, 11), (20, 22)),
+            link(31, (20, 21), (10, 12)),
+            PipewireUpdateEvent::Synced,
+        ] {
+            registry.process_pipewire_event(event).await;
+        }
+        registry
+    }
+
+    fn node_ids(registry: &PipewireRegistry) -> Vec<u32> {
+        registry.nodes.keys().copied().collect()
+    }
+
+    fn port_ids(registry: &PipewireRegistry) -> Vec<u32> {
+        registry.ports.keys().copied().collect()
+    }
+
+    fn link_ids(registry: &PipewireRegistry) -> Vec<u32> {
+        registry.links.keys().copied().collect()
+    }
+
+    #[tokio::test]
+    async fn removing_a_node_removes_its_ports_and_links() {
+        let mut registry = linked_nodes().await;
+
+        registry
+            .process_pipewire_event(PipewireUpdateEvent::GlobalRemoved { global_id: 10 })
+            .await;
+
+        assert_eq!(node_ids(&registry), vec![20]);
+        assert_eq!(port_ids(&registry), vec![21, 22]);
+        assert!(link_ids(&registry).is_empty());
+    }
+
+    #[tokio::test]
+    async fn removing_a_port_removes_its_links() {
+        let mut registry = linked_nodes().await;
+
+        registry
+            .process_pipewire_event(PipewireUpdateEvent::GlobalRemoved { global_id: 11 })
+            .await;
+
+        assert_eq!(registry.nodes.len(), 2);
+        assert!(!port_ids(&registry).contains(&11));
+        assert_eq!(link_ids(&registry), vec![31]);
+    }
+
+    #[tokio::test]
+    async fn removing_a_link_keeps_its_ports() {
+        let mut registry = linked_nodes().await;
+
+        registry
+            .process_pipewire_event(PipewireUpdateEvent::GlobalRemoved { global_id: 30 })
+            .await;
+
+        assert_eq!(port_ids(&registry).len(), 4);
+        assert_eq!(link_ids(&registry), vec![31]);
+    }
+
+    #[tokio::test]
+    async fn a_repeated_global_updates_the_entity_in_place() {
+        let mut registry = linked_nodes().await;
+        let mut renamed = node(10);
+        if let PipewireUpdateEvent::Node { node_name, .. } = &mut renamed {
+            *node_name = "renamed".to_owned();
+        }
+
+        registry.process_pipewire_event(renamed).await;
+        registry
+            .process_pipewire_event(port(11, 10, 0, "out"))
+            .await;
+
+        assert_eq!(node_ids(&registry), vec![10, 20]);
+        assert_eq!(registry.nodes[&10].node_name, "renamed");
+        assert_eq!(port_ids(&registry), vec![11, 12, 21, 22]);
+        assert_eq!(link_ids(&registry), vec![30, 31]);
+    }
+
+    #[tokio::test]
+    async fn a_link_waiter_gets_the_link_once_it_is_added() {
+        let mut registry = linked_nodes().await;
+        registry
+            .process_pipewire_event(PipewireUpdateEvent::GlobalRemoved { global_id: 31 })
+            .await;
+        let (reply_sender, reply_receiver) = tokio::sync::oneshot::channel();
+        registry
+            .process_registry_request(PipewireRegistryRequests::WaitForLink {
+                output_port_id: 21,
+                input_port_id: 12,
+                reply_sender,
+            })
+            .await;
+        let Ok(Err(mut link_receiver)) = reply_receiver.await else {
+            panic!("the link does not exist yet");
+        };
+
+        registry
+            .process_pipewire_event(link(32, (10, 11), (20, 22)))
+            .await;
+        assert!(link_receiver.try_recv().is_err());
+        registry
+            .process_pipewire_event(link(33, (20, 21), (10, 12)))
+            .await;
+
+        assert_eq!(link_receiver.await.unwrap().global_id, 33);
+        assert!(registry.link_waiters.is_empty());
+    }
+
+    #[tokio::test]
+    async fn graph_queries_fail_while_pipewire_is_disconnected() {
+        let mut registry = registry();
+        registry
+            .process_pipewire_event(PipewireUpdateEvent::Connected)
+            .await;
+        let (held_sender, held_receiver) = tokio::sync::oneshot::channel();
+        registry
+            .process_registry_request(PipewireRegistryRequests::ListPorts {
+                filter: ListFilter::default(),
+                reply_sender: held_sender,
+            })
+            .await;
+
+        registry
+            .process_pipewire_event(PipewireUpdateEvent::Disconnected)
+            .await;
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        registry
+            .process_registry_request(PipewireRegistryRequests::ListPorts {
+                filter: ListFilter::default(),
+                reply_sender: sender,
+            })
+            .await;
+
+        assert!(held_receiver.await.is_err());
+        assert!(receiver.await.is_err());
+        assert!(registry.pending_requests.is_empty());
+    }
+
+    async fn list_ports(registry: &mut PipewireRegistry, filter: ListFilter) -> Vec<u32> {
+        let (reply_sender, reply_receiver) = tokio::sync::oneshot::channel();
+        registry
+            .process_registry_request(PipewireRegistryRequests::ListPorts {
+                filter,
+                reply_sender,
+            })
+            .await;
+        reply_receiver
+            .await
+            .unwrap()
+            .iter()
+            .map(|port| port.global_id)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn name_patterns_match_port_names_and_aliases() {
+        let mut registry = linked_nodes().await;
+
+        let glob = ListFilter {
+            name: Some(NamePattern::Glob(glob::Pattern::new("port-2?").unwrap())),
+            ..ListFilter::default()
+        };
+        let regex = ListFilter {
+            name: Some(NamePattern::Regex(
+                regex::Regex::new("^port-1[12]$").unwrap(),
+            )),
+            ..ListFilter::default()
+        };
+
+        assert_eq!(list_ports(&mut registry, glob).await, vec![21, 22]);
+        assert_eq!(list_ports(&mut registry, regex).await, vec![11, 12]);
+    }
+
+    #[tokio::test]
+    async fn ports_match_the_device_owning_their_node() {
+        let mut registry = linked_nodes().await;
+        registry
+            .process_pipewire_event(PipewireUpdateEvent::Device {
+                global_id: 5,
+                name: String::from("alsa_card"),
+                factory_id: 0,
+                client_id: 0,
+                description: String::new(),
+                nick: String::new(),
+                media_class: String::from("Audio/Device"),
+                object_serial: 5,
+            })
+            .await;
+        registry
+            .process_pipewire_event(PipewireUpdateEvent::Properties {
+                global_id: 20,
+                properties: BTreeMap::from([(String::from("device.id"), String::from("5"))]),
+            })
+            .await;
+
+        let owned = ListFilter {
+            device_id: Some(5),
+            ..ListFilter::default()
+        };
+        let other = ListFilter {
+            device_id: Some(6),
+            ..ListFilter::default()
+        };
+
+        assert_eq!(list_ports(&mut registry, owned).await, vec![21, 22]);
+        assert!(list_ports(&mut registry, other).await.is_empty());
+    }
+
+    fn node_info(global_id: u32, input_ports: u32, output_ports: u32) -> PipewireUpdateEvent {
+        PipewireUpdateEvent::NodeInfo {
+            global_id,
+            state: NodeState::Idle,
+            input_ports,
+            output_ports,
+            properties: None,
+        }
+    }
+
+    fn channel_port(
+        global_id: u32,
+        node_id: u32,
+        direction: &str,
+        channel: &str,
+    ) -> PipewireUpdateEvent {
+        let mut event = port(global_id, node_id, 0, direction);
+        if let PipewireUpdateEvent::Port { audio_channel, .. } = &mut event {
+            *audio_channel = channel.to_owned();
+        }
+        event
+    }
+
+    fn layout_links(registry: &PipewireRegistry) -> Vec<(String, String)> {
+        registry
+            .patchbay
+            .links()
+            .map(|link| (link.output_port.clone(), link.input_port.clone()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn layout_waits_for_all_ports_of_a_node() {
+        let mut registry = registry();
+        registry.patchbay.set_layout(vec![NodeLink {
+            output_node: String::from("node-10"),
+            input_node: String::from("node-20"),
+        }]);
+        for event in [
+            PipewireUpdateEvent::Connected,
+            node(10),
+            node_info(10, 0, 2),
+            node(20),
+            node_info(20, 2, 0),
+            channel_port(21, 20, "in", "FL"),
+            channel_port(22, 20, "in", "FR"),
+            channel_port(11, 10, "out", "FL"),
+        ] {
+            registry.process_pipewire_event(event).await;
+        }
+
+        registry.resolve_layout();
+        assert!(layout_links(&registry).is_empty());
+
+        registry
+            .process_pipewire_event(channel_port(12, 10, "out", "FR"))
+            .await;
+        registry.resolve_layout();
+        assert_eq!(
+            layout_links(&registry),
+            vec![
+                (String::from("port-11"), String::from("port-21")),
+                (String::from("port-12"), String::from("port-22")),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn graph_leaves_out_links_to_unknown_ports() {
+        let mut registry = linked_nodes().await;
+        registry
+            .process_pipewire_event(link(32, (10, 11), (20, 99)))
+            .await;
+
+        let graph = registry.graph();
+        let graph_link_ids: Vec<u32> = graph.links.iter().map(|link| link.global_id).collect();
+        assert_eq!(graph_link_ids, vec![30, 31]);
+        assert!(!graph.to_dot().contains("port_99"));
+    }
 }
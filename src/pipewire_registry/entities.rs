@@ -1,7 +1,24 @@
+use std::collections::BTreeMap;
+
+pub type Properties = BTreeMap<String, String>;
+
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionState {
+    Disconnected,
+    Connected,
+}
+
+#[derive(Debug, Clone)]
+pub struct RegistryStatus {
+    pub connection_state: ConnectionState,
+    pub ready: bool,
+}
+
 #[derive(Debug, Clone)]
 pub struct Application {
-    pub object_serial: u16,
-    pub module_id: u16,
+    pub global_id: u32,
+    pub object_serial: u64,
+    pub module_id: Option<u32>,
     pub pipewire_protocol: String,
     pub pipewire_sec_pid: String,
     pub pipewire_sec_uid: String,
@@ -13,13 +30,14 @@ pub struct Application {
 
 #[derive(Debug, Clone)]
 pub struct Device {
+    pub global_id: u32,
     pub name: String,
-    pub factory_id: u16,
-    pub client_id: u16,
+    pub factory_id: u32,
+    pub client_id: u32,
     pub description: String,
     pub nick: String,
     pub media_class: String,
-    pub object_serial: u16,
+    pub object_serial: u64,
 }
 
 #[derive(Copy, Debug, Clone, Ord, PartialEq, PartialOrd, Eq)]
@@ -31,9 +49,10 @@ pub enum PortDirection {
 
 #[derive(Debug, Clone)]
 pub struct Port {
-    pub id: u16,
-    pub node_id: u16,
-    pub object_serial: u16,
+    pub global_id: u32,
+    pub id: u32,
+    pub node_id: u32,
+    pub object_serial: u64,
     pub name: String,
     pub direction: PortDirection,
     pub physical: bool,
@@ -44,24 +63,99 @@ pub struct Port {
     pub audio_channel: String,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NodeState {
+    Unknown,
+    Error(String),
+    Creating,
+    Suspended,
+    Idle,
+    Running,
+}
+
 #[derive(Debug, Clone)]
 pub struct Node {
-    pub object_serial: u16,
-    pub factory_id: u16,
-    pub client_id: u16,
+    pub global_id: u32,
+    pub object_serial: u64,
+    pub factory_id: Option<u32>,
+    pub client_id: Option<u32>,
     pub client_api: String,
     pub application_name: String,
     pub node_name: String,
     pub media_class: String,
+    pub state: NodeState,
+    pub input_ports: u32,
+    pub output_ports: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkState {
+    Unknown,
+    Error(String),
+    Unlinked,
+    Init,
+    Negotiating,
+    Allocating,
+    Paused,
+    Active,
+}
+
+impl LinkState {
+    /// Links that failed or never got past negotiation. Links whose proxy has
+    /// not reported yet are not counted.
+    pub fn is_unhealthy(&self) -> bool {
+        matches!(
+            self,
+            LinkState::Error(_)
+                | LinkState::Unlinked
+                | LinkState::Init
+                | LinkState::Negotiating
+                | LinkState::Allocating
+        )
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Link {
-    pub object_serial: u16,
-    pub factory_id: u16,
-    pub client_id: u16,
-    pub output_port_id: u16,
-    pub input_port_id: u16,
-    pub output_node_id: u16,
-    pub input_node_id: u16,
+    pub global_id: u32,
+    pub object_serial: u64,
+    pub factory_id: u32,
+    pub client_id: Option<u32>,
+    pub output_port_id: u32,
+    pub input_port_id: u32,
+    pub output_node_id: u32,
+    pub input_node_id: u32,
+    pub state: LinkState,
+    pub format: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Module {
+    pub global_id: u32,
+    pub object_serial: u64,
+    pub name: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Factory {
+    pub global_id: u32,
+    pub object_serial: u64,
+    pub module_id: Option<u32>,
+    pub name: String,
+    pub type_name: String,
+    pub type_version: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Metadata {
+    pub global_id: u32,
+    pub object_serial: u64,
+    pub name: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Core {
+    pub global_id: u32,
+    pub object_serial: u64,
+    pub name: String,
 }
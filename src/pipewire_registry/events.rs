@@ -0,0 +1,74 @@
+use crate::pipewire_registry::entities::{
+    Application, Core, Device, Factory, Link, Metadata, Module, Node, Port, Properties,
+    RegistryStatus,
+};
+
+#[derive(Debug, Clone)]
+pub enum RegistryEntity {
+    Node(Node),
+    Port(Port),
+    Link(Link),
+    Device(Device),
+    Application(Application),
+    Module(Module),
+    Factory(Factory),
+    Metadata(Metadata),
+    Core(Core),
+}
+
+impl RegistryEntity {
+    pub fn global_id(&self) -> u32 {
+        match self {
+            RegistryEntity::Node(node) => node.global_id,
+            RegistryEntity::Port(port) => port.global_id,
+            RegistryEntity::Link(link) => link.global_id,
+            RegistryEntity::Device(device) => device.global_id,
+            RegistryEntity::Application(application) => application.global_id,
+            RegistryEntity::Module(module) => module.global_id,
+            RegistryEntity::Factory(factory) => factory.global_id,
+            RegistryEntity::Metadata(metadata) => metadata.global_id,
+            RegistryEntity::Core(core) => core.global_id,
+        }
+    }
+
+    pub fn object_serial(&self) -> u64 {
+        match self {
+            RegistryEntity::Node(node) => node.object_serial,
+            RegistryEntity::Port(port) => port.object_serial,
+            RegistryEntity::Link(link) => link.object_serial,
+            RegistryEntity::Device(device) => device.object_serial,
+            RegistryEntity::Application(application) => application.object_serial,
+            RegistryEntity::Module(module) => module.object_serial,
+            RegistryEntity::Factory(factory) => factory.object_serial,
+            RegistryEntity::Metadata(metadata) => metadata.object_serial,
+            RegistryEntity::Core(core) => core.object_serial,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RegistrySnapshot {
+    pub status: RegistryStatus,
+    pub nodes: Vec<Node>,
+    pub ports: Vec<Port>,
+    pub links: Vec<Link>,
+    pub devices: Vec<Device>,
+    pub applications: Vec<Application>,
+    pub modules: Vec<Module>,
+    pub factories: Vec<Factory>,
+    pub metadata: Vec<Metadata>,
+    pub cores: Vec<Core>,
+}
+
+#[derive(Debug, Clone)]
+pub enum RegistryEvent {
+    Snapshot(RegistrySnapshot),
+    Added(RegistryEntity),
+    Changed(RegistryEntity),
+    Removed(RegistryEntity),
+    PropertiesChanged {
+        global_id: u32,
+        properties: Properties,
+    },
+    StatusChanged(RegistryStatus),
+}
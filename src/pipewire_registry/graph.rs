@@ -0,0 +1,321 @@
+use std::fmt::Write;
+
+use serde_json::json;
+
+use crate::pipewire_registry::entities::{
+    Device, Link, LinkState, Node, NodeState, Port, PortDirection,
+};
+
+#[derive(Debug, Clone)]
+pub struct GraphNode {
+    pub node: Node,
+    pub device_id: Option<u32>,
+    pub ports: Vec<Port>,
+}
+
+/// The devices, nodes, ports and links of the registry arranged for
+/// rendering. Nodes only refer to devices that are part of the graph, and
+/// links only to ports that are.
+#[derive(Debug, Clone)]
+pub struct RegistryGraph {
+    pub devices: Vec<Device>,
+    pub nodes: Vec<GraphNode>,
+    pub links: Vec<Link>,
+}
+
+impl RegistryGraph {
+    /// Renders the graph for Graphviz. Devices and nodes are clusters, every
+    /// port is a record and links are edges between ports.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph pipewire {\n");
+        dot.push_str("    rankdir=LR;\n");
+        dot.push_str("    node [shape=record];\n");
+
+        for device in self.devices.iter() {
+            let _ = writeln!(dot, "    subgraph cluster_device_{} {{", device.global_id);
+            let _ = writeln!(dot, "        label=\"{}\";", quote(&device.name));
+            for node in self
+                .nodes
+                .iter()
+                .filter(|node| node.device_id == Some(device.global_id))
+            {
+                write_node(&mut dot, node, "        ");
+            }
+            dot.push_str("    }\n");
+        }
+        for node in self.nodes.iter().filter(|node| node.device_id.is_none()) {
+            write_node(&mut dot, node, "    ");
+        }
+
+        for link in self.links.iter() {
+            let color = match link.state {
+                LinkState::Error(_) => "red",
+                _ if link.state.is_unhealthy() => "orange",
+                _ => "black",
+            };
+            let _ = writeln!(
+                dot,
+                "    port_{} -> port_{} [color={color}, tooltip=\"link {} {}\"];",
+                link.output_port_id,
+                link.input_port_id,
+                link.global_id,
+                link_state_name(&link.state)
+            );
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+
+    pub fn to_json(&self) -> String {
+        let devices: Vec<_> = self
+            .devices
+            .iter()
+            .map(|device| {
+                json!({
+                    "id": device.global_id,
+                    "name": device.name,
+                    "description": device.description,
+                    "media_class": device.media_class,
+                })
+            })
+            .collect();
+        let nodes: Vec<_> = self
+            .nodes
+            .iter()
+            .map(|node| {
+                let ports: Vec<_> = node
+                    .ports
+                    .iter()
+                    .map(|port| {
+                        json!({
+                            "id": port.global_id,
+                            "name": port.name,
+                            "alias": port.alias,
+                            "direction": direction_name(port.direction),
+                            "audio_channel": port.audio_channel,
+                            "physical": port.physical,
+                        })
+                    })
+                    .collect();
+                json!({
+                    "id": node.node.global_id,
+                    "name": node.node.node_name,
+                    "media_class": node.node.media_class,
+                    "application": node.node.application_name,
+                    "device_id": node.device_id,
+                    "state": node_state_name(&node.node.state),
+                    "error": match &node.node.state {
+                        NodeState::Error(error) => Some(error),
+                        _ => None,
+                    },
+                    "ports": ports,
+                })
+            })
+            .collect();
+        let links: Vec<_> = self
+            .links
+            .iter()
+            .map(|link| {
+                json!({
+                    "id": link.global_id,
+                    "output_node": link.output_node_id,
+                    "output_port": link.output_port_id,
+                    "input_node": link.input_node_id,
+                    "input_port": link.input_port_id,
+                    "state": link_state_name(&link.state),
+                    "error": match &link.state {
+                        LinkState::Error(error) => Some(error),
+                        _ => None,
+                    },
+                    "format": link.format,
+                })
+            })
+            .collect();
+
+        format!(
+            "{:#}",
+            json!({
+                "devices": devices,
+                "nodes": nodes,
+                "links": links,
+            })
+        )
+    }
+}
+
+fn write_node(dot: &mut String, node: &GraphNode, indent: &str) {
+    let _ = writeln!(
+        dot,
+        "{indent}subgraph cluster_node_{} {{",
+        node.node.global_id
+    );
+    let _ = writeln!(
+        dot,
+        "{indent}    label=\"{} ({})\";",
+        quote(&node.node.node_name),
+        node_state_name(&node.node.state)
+    );
+    for port in node.ports.iter() {
+        let _ = writeln!(
+            dot,
+            "{indent}    port_{} [label=\"{{{}|{}}}\"];",
+            port.global_id,
+            direction_name(port.direction),
+            record_field(&quote(&port.name))
+        );
+    }
+    let _ = writeln!(dot, "{indent}}}");
+}
+
+fn quote(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Record labels give `{`, `}`, `|`, `<` and `>` a meaning of their own.
+fn record_field(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '{' | '}' | '|' | '<' | '>') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn direction_name(direction: PortDirection) -> &'static str {
+    match direction {
+        PortDirection::In => "in",
+        PortDirection::Out => "out",
+        PortDirection::Unknown => "unknown",
+    }
+}
+
+fn node_state_name(state: &NodeState) -> &'static str {
+    match state {
+        NodeState::Unknown => "unknown",
+        NodeState::Error(_) => "error",
+        NodeState::Creating => "creating",
+        NodeState::Suspended => "suspended",
+        NodeState::Idle => "idle",
+        NodeState::Running => "running",
+    }
+}
+
+fn link_state_name(state: &LinkState) -> &'static str {
+    match state {
+        LinkState::Unknown => "unknown",
+        LinkState::Error(_) => "error",
+        LinkState::Unlinked => "unlinked",
+        LinkState::Init => "init",
+        LinkState::Negotiating => "negotiating",
+        LinkState::Allocating => "allocating",
+        LinkState::Paused => "paused",
+        LinkState::Active => "active",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph() -> RegistryGraph {
+        let node = Node {
+            global_id: 10,
+            object_serial: 10,
+            factory_id: None,
+            client_id: None,
+            client_api: String::new(),
+            application_name: String::from("player"),
+            node_name: String::from("player \"main\""),
+            media_class: String::from("Stream/Output/Audio"),
+            state: NodeState::Error(String::from("no memory")),
+            input_ports: 1,
+            output_ports: 1,
+        };
+        let port = |global_id: u32, name: &str, direction: PortDirection| Port {
+            global_id,
+            id: 0,
+            node_id: 10,
+            object_serial: u64::from(global_id),
+            name: name.to_owned(),
+            direction,
+            physical: false,
+            alias: String::new(),
+            group: String::new(),
+            path: String::new(),
+            dsp_format: String::new(),
+            audio_channel: String::from("FL"),
+        };
+        RegistryGraph {
+            devices: Vec::new(),
+            nodes: vec![GraphNode {
+                node,
+                device_id: None,
+                ports: vec![
+                    port(11, "in_{FL|FR}", PortDirection::In),
+                    port(12, "out\\1", PortDirection::Out),
+                ],
+            }],
+            links: vec![Link {
+                global_id: 30,
+                object_serial: 30,
+                factory_id: 0,
+                client_id: None,
+                output_port_id: 12,
+                input_port_id: 11,
+                output_node_id: 10,
+                input_node_id: 10,
+                state: LinkState::Error(String::from("no format")),
+                format: None,
+            }],
+        }
+    }
+
+    #[test]
+    fn quotes_escape_backslashes_and_quotes() {
+        assert_eq!(quote(r#"a "b" \c"#), r#"a \"b\" \\c"#);
+    }
+
+    #[test]
+    fn record_fields_escape_record_syntax() {
+        assert_eq!(record_field("in_{FL|FR}<1>"), r"in_\{FL\|FR\}\<1\>");
+    }
+
+    #[test]
+    fn dot_escapes_labels_and_colours_failed_links() {
+        let dot = graph().to_dot();
+
+        assert!(dot.contains(r#"label="player \"main\" (error)";"#));
+        assert!(dot.contains(r#"port_11 [label="{in|in_\{FL\|FR\}}"];"#));
+        assert!(dot.contains(r#"port_12 [label="{out|out\\1}"];"#));
+        assert!(dot.contains(r#"port_12 -> port_11 [color=red, tooltip="link 30 error"];"#));
+    }
+
+    #[test]
+    fn json_holds_nodes_with_their_ports_and_links() {
+        let json: serde_json::Value = serde_json::from_str(&graph().to_json()).unwrap();
+
+        assert_eq!(json["devices"], json!([]));
+        assert_eq!(json["nodes"][0]["name"], "player \"main\"");
+        assert_eq!(json["nodes"][0]["state"], "error");
+        assert_eq!(json["nodes"][0]["error"], "no memory");
+        assert_eq!(json["nodes"][0]["device_id"], serde_json::Value::Null);
+        assert_eq!(json["nodes"][0]["ports"][0]["name"], "in_{FL|FR}");
+        assert_eq!(json["nodes"][0]["ports"][1]["direction"], "out");
+        assert_eq!(
+            json["links"],
+            json!([{
+                "id": 30,
+                "output_node": 10,
+                "output_port": 12,
+                "input_node": 10,
+                "input_port": 11,
+                "state": "error",
+                "error": "no format",
+                "format": null,
+            }])
+        );
+    }
+}
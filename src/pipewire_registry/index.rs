@@ -0,0 +1,87 @@
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A secondary key pointing at the global ids of the objects carrying it.
+/// Keys are not required to be unique, several nodes can share a name.
+pub struct Index<K: Ord> {
+    entries: BTreeMap<K, BTreeSet<u32>>,
+}
+
+impl<K: Ord> Default for Index<K> {
+    fn default() -> Self {
+        Index {
+            entries: BTreeMap::new(),
+        }
+    }
+}
+
+impl<K: Ord> Index<K> {
+    pub fn insert(&mut self, key: K, global_id: u32) {
+        self.entries.entry(key).or_default().insert(global_id);
+    }
+
+    pub fn remove(&mut self, key: &K, global_id: u32) {
+        if let Some(global_ids) = self.entries.get_mut(key) {
+            global_ids.remove(&global_id);
+            if global_ids.is_empty() {
+                self.entries.remove(key);
+            }
+        }
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Vec<u32>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.entries
+            .get(key)
+            .map(|global_ids| global_ids.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_point_at_every_global_carrying_them() {
+        let mut index = Index::default();
+        index.insert(String::from("speakers"), 40);
+        index.insert(String::from("speakers"), 30);
+        index.insert(String::from("microphone"), 50);
+
+        assert_eq!(index.get("speakers"), vec![30, 40]);
+        assert_eq!(index.get("microphone"), vec![50]);
+        assert!(index.get("headphones").is_empty());
+    }
+
+    #[test]
+    fn removing_the_last_global_removes_the_key() {
+        let mut index = Index::default();
+        index.insert(7_u64, 30);
+        index.insert(7_u64, 40);
+
+        index.remove(&7, 30);
+        assert_eq!(index.get(&7), vec![40]);
+        index.remove(&7, 40);
+        assert!(index.get(&7).is_empty());
+        assert!(index.entries.is_empty());
+    }
+
+    #[test]
+    fn removing_an_unknown_global_keeps_the_others() {
+        let mut index = Index::default();
+        index.insert(String::from("speakers"), 30);
+
+        index.remove(&String::from("speakers"), 40);
+        index.remove(&String::from("microphone"), 30);
+
+        assert_eq!(index.get("speakers"), vec![30]);
+    }
+}
@@ -0,0 +1,56 @@
+use glob::Pattern;
+use regex::Regex;
+
+use crate::pipewire_registry::entities::PortDirection;
+
+#[derive(Debug, Clone)]
+pub enum NamePattern {
+    Glob(Pattern),
+    Regex(Regex),
+}
+
+impl NamePattern {
+    pub fn matches(&self, name: &str) -> bool {
+        match self {
+            NamePattern::Glob(pattern) => pattern.matches(name),
+            NamePattern::Regex(regex) => regex.is_match(name),
+        }
+    }
+}
+
+/// Criteria for the list requests, every criterion that is set has to
+/// match. Criteria an object type has no attribute for are ignored for it.
+#[derive(Debug, Clone, Default)]
+pub struct ListFilter {
+    pub media_class: Option<String>,
+    pub name: Option<NamePattern>,
+    pub node_id: Option<u32>,
+    pub direction: Option<PortDirection>,
+    pub physical: Option<bool>,
+    pub terminal: Option<bool>,
+    pub monitor: Option<bool>,
+    pub application: Option<String>,
+    pub device_id: Option<u32>,
+    pub dsp_format: Option<String>,
+    pub unhealthy_only: bool,
+}
+
+impl ListFilter {
+    /// Whether any of the given names matches the name pattern.
+    pub fn matches_name(&self, names: &[&str]) -> bool {
+        match &self.name {
+            Some(pattern) => names.iter().any(|name| pattern.matches(name)),
+            None => true,
+        }
+    }
+
+    /// Whether the filter has criteria that are checked against the node
+    /// owning a port or a link end.
+    pub fn has_node_criteria(&self) -> bool {
+        self.media_class.is_some() || self.application.is_some() || self.device_id.is_some()
+    }
+}
+
+pub fn matches<T: PartialEq + ?Sized>(expected: Option<&T>, actual: &T) -> bool {
+    expected.is_none() || expected == Some(actual)
+}
@@ -0,0 +1,288 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// A connection that should exist whenever both of its ports do. Ports are
+/// matched by the name of their node and their own name, so the link is
+/// recreated when an application restarts with new ids.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct DesiredLink {
+    pub output_node: String,
+    pub output_port: String,
+    pub input_node: String,
+    pub input_port: String,
+}
+
+impl fmt::Display for DesiredLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{} -> {}:{}",
+            self.output_node, self.output_port, self.input_node, self.input_port
+        )
+    }
+}
+
+/// A connection between two nodes that is made between their ports once
+/// both exist. Which ports are linked follows from the ports themselves.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct NodeLink {
+    pub output_node: String,
+    pub input_node: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum DesiredLinkState {
+    /// At least one of the ports does not exist yet, or the link is being
+    /// created.
+    Pending,
+    Satisfied {
+        link_id: u32,
+    },
+    Broken {
+        reason: String,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct DesiredLinkStatus {
+    pub link: DesiredLink,
+    pub state: DesiredLinkState,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct PatchbayFile {
+    #[serde(default)]
+    links: Vec<DesiredLink>,
+}
+
+/// The desired connections together with the links the registry is
+/// currently creating for them and the attempts that failed. Every creation
+/// attempt gets its own number, so a late result of an earlier attempt does
+/// not settle a newer one.
+///
+/// The node links from the pmx config form the layout. It is read again on
+/// every start instead of being saved, and the registry turns it into port
+/// links that are enforced like the saved ones.
+pub struct Patchbay {
+    path: Option<PathBuf>,
+    links: BTreeSet<DesiredLink>,
+    layout: BTreeSet<NodeLink>,
+    layout_links: BTreeSet<DesiredLink>,
+    creating: BTreeMap<DesiredLink, u64>,
+    next_attempt: u64,
+    failures: BTreeMap<DesiredLink, String>,
+}
+
+impl Patchbay {
+    /// Loads the desired connections, a missing file is an empty patchbay.
+    /// Without a path the patchbay is kept in memory only.
+    pub fn load(path: Option<PathBuf>) -> Result<Patchbay> {
+        let links = match &path {
+            Some(path) if path.exists() => {
+                let content = fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read patchbay {}", path.display()))?;
+                let file: PatchbayFile = toml::from_str(&content)
+                    .with_context(|| format!("Invalid patchbay {}", path.display()))?;
+                file.links.into_iter().collect()
+            }
+            _ => BTreeSet::new(),
+        };
+
+        Ok(Patchbay {
+            path,
+            links,
+            layout: BTreeSet::new(),
+            layout_links: BTreeSet::new(),
+            creating: BTreeMap::new(),
+            next_attempt: 0,
+            failures: BTreeMap::new(),
+        })
+    }
+
+    pub fn links(&self) -> impl Iterator<Item = &DesiredLink> {
+        self.links.union(&self.layout_links)
+    }
+
+    pub fn layout(&self) -> impl Iterator<Item = &NodeLink> {
+        self.layout.iter()
+    }
+
+    pub fn set_layout(&mut self, layout: Vec<NodeLink>) {
+        self.layout = layout.into_iter().collect();
+    }
+
+    /// Replaces the port links of the layout with the ones its nodes have
+    /// now. Links that are no longer part of it are not created anymore.
+    pub fn set_layout_links(&mut self, layout_links: BTreeSet<DesiredLink>) {
+        self.layout_links = layout_links;
+        let desired =
+            |link: &DesiredLink| self.links.contains(link) || self.layout_links.contains(link);
+        self.creating.retain(|link, _| desired(link));
+        self.failures.retain(|link, _| desired(link));
+    }
+
+    /// Adds a desired link and saves the patchbay. Returns false when the
+    /// link was already part of it.
+    pub fn add(&mut self, link: DesiredLink) -> Result<bool> {
+        if !self.links.insert(link.clone()) {
+            return Ok(false);
+        }
+        if let Err(error) = self.save() {
+            self.links.remove(&link);
+            return Err(error);
+        }
+        Ok(true)
+    }
+
+    /// Removes a desired link and saves the patchbay. Returns false when the
+    /// link was not part of it.
+    pub fn remove(&mut self, link: &DesiredLink) -> Result<bool> {
+        if !self.links.remove(link) {
+            return Ok(false);
+        }
+        if let Err(error) = self.save() {
+            self.links.insert(link.clone());
+            return Err(error);
+        }
+        if self.layout_links.contains(link) {
+            return Ok(true);
+        }
+        self.creating.remove(link);
+        self.failures.remove(link);
+        Ok(true)
+    }
+
+    pub fn is_creating(&self, link: &DesiredLink) -> bool {
+        self.creating.contains_key(link)
+    }
+
+    pub fn failure(&self, link: &DesiredLink) -> Option<&str> {
+        self.failures.get(link).map(String::as_str)
+    }
+
+    /// Marks the link as being created and returns the number of the
+    /// attempt.
+    pub fn creation_started(&mut self, link: DesiredLink) -> u64 {
+        self.next_attempt += 1;
+        self.creating.insert(link, self.next_attempt);
+        self.next_attempt
+    }
+
+    pub fn creation_failed(&mut self, link: &DesiredLink, attempt: u64, reason: String) {
+        if self.end_attempt(link, attempt)
+            && (self.links.contains(link) || self.layout_links.contains(link))
+        {
+            self.failures.insert(link.clone(), reason);
+        }
+    }
+
+    /// The link was created but did not show up in the registry in time,
+    /// for instance because it was destroyed right away. Returns true when
+    /// the attempt was still running, the link is then enforced again.
+    pub fn creation_expired(&mut self, link: &DesiredLink, attempt: u64) -> bool {
+        self.end_attempt(link, attempt)
+    }
+
+    fn end_attempt(&mut self, link: &DesiredLink, attempt: u64) -> bool {
+        if self.creating.get(link) != Some(&attempt) {
+            return false;
+        }
+        self.creating.remove(link);
+        true
+    }
+
+    /// The link exists, or one of its ports went away. Either way the next
+    /// time both ports are there a new attempt is made.
+    pub fn settled(&mut self, link: &DesiredLink) {
+        self.creating.remove(link);
+        self.failures.remove(link);
+    }
+
+    pub fn reset(&mut self) {
+        self.creating.clear();
+        self.failures.clear();
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)
+                .with_context(|| format!("Failed to create {}", directory.display()))?;
+        }
+        let file = PatchbayFile {
+            links: self.links.iter().cloned().collect(),
+        };
+        let content = toml::to_string_pretty(&file).context("Failed to serialize patchbay")?;
+        fs::write(path, content)
+            .with_context(|| format!("Failed to write patchbay {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn desired_link() -> DesiredLink {
+        DesiredLink {
+            output_node: String::from("player"),
+            output_port: String::from("output_FL"),
+            input_node: String::from("speakers"),
+            input_port: String::from("playback_FL"),
+        }
+    }
+
+    #[test]
+    fn expired_creation_is_no_longer_pending() {
+        let mut patchbay = Patchbay::load(None).unwrap();
+        let link = desired_link();
+        patchbay.add(link.clone()).unwrap();
+
+        let attempt = patchbay.creation_started(link.clone());
+        assert!(patchbay.is_creating(&link));
+        assert!(patchbay.creation_expired(&link, attempt));
+        assert!(!patchbay.is_creating(&link));
+        assert_eq!(patchbay.failure(&link), None);
+    }
+
+    #[test]
+    fn late_results_do_not_end_a_newer_attempt() {
+        let mut patchbay = Patchbay::load(None).unwrap();
+        let link = desired_link();
+        patchbay.add(link.clone()).unwrap();
+
+        let first = patchbay.creation_started(link.clone());
+        patchbay.settled(&link);
+        let second = patchbay.creation_started(link.clone());
+
+        assert!(!patchbay.creation_expired(&link, first));
+        patchbay.creation_failed(&link, first, String::from("late"));
+        assert!(patchbay.is_creating(&link));
+        assert_eq!(patchbay.failure(&link), None);
+
+        patchbay.creation_failed(&link, second, String::from("no such port"));
+        assert!(!patchbay.is_creating(&link));
+        assert_eq!(patchbay.failure(&link), Some("no such port"));
+    }
+
+    #[test]
+    fn layout_links_follow_the_nodes_ports() {
+        let mut patchbay = Patchbay::load(None).unwrap();
+        let link = desired_link();
+        patchbay.set_layout_links(BTreeSet::from([link.clone()]));
+        assert_eq!(patchbay.links().collect::<Vec<_>>(), vec![&link]);
+
+        let attempt = patchbay.creation_started(link.clone());
+        patchbay.set_layout_links(BTreeSet::new());
+        assert_eq!(patchbay.links().count(), 0);
+        assert!(!patchbay.is_creating(&link));
+        patchbay.creation_failed(&link, attempt, String::from("late"));
+        assert_eq!(patchbay.failure(&link), None);
+    }
+}
@@ -0,0 +1,71 @@
+use std::io;
+
+use anyhow::{anyhow, Context, Result};
+use fr_logging::Logger;
+
+use crate::pipewire_registry::NodeLink;
+
+/// Reads the pmx mixer config and turns its channels and buses into the
+/// connections between their nodes:
+///
+/// - the source of a channel feeds the channel,
+/// - a channel feeds every bus it is routed to,
+/// - a bus feeds its sink.
+///
+/// Channel and bus nodes are named after their definition. The ports of two
+/// connected nodes are paired by their audio channels once the nodes exist,
+/// so the layout works for any channel count.
+///
+/// Without a pmx config file there is no layout. A config that can not be
+/// read or refers to buses it does not define is an error.
+pub fn load_layout(logger: &Logger) -> Result<Vec<NodeLink>> {
+    let config = match fr_pmx_config_lib::read_config() {
+        Ok(config) => config,
+        Err(error) => {
+            let error = anyhow!(error);
+            if is_missing_file(&error) {
+                logger.log_info(&format!("No pmx config, there is no link layout: {error}"));
+                return Ok(Vec::new());
+            }
+            return Err(error).context("Failed to read the pmx config");
+        }
+    };
+
+    let mut links = Vec::new();
+    for channel in config.channels.iter() {
+        if let Some(source) = &channel.source {
+            links.push(node_link(source, &channel.name));
+        }
+        for bus in channel.buses.iter() {
+            if !config.buses.iter().any(|b| &b.name == bus) {
+                return Err(anyhow!(
+                    "Channel {} is routed to unknown bus {bus}",
+                    channel.name
+                ));
+            }
+            links.push(node_link(&channel.name, bus));
+        }
+    }
+    for bus in config.buses.iter() {
+        if let Some(sink) = &bus.sink {
+            links.push(node_link(&bus.name, sink));
+        }
+    }
+
+    Ok(links)
+}
+
+fn is_missing_file(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        cause
+            .downcast_ref::<io::Error>()
+            .is_some_and(|error| error.kind() == io::ErrorKind::NotFound)
+    })
+}
+
+fn node_link(output_node: &str, input_node: &str) -> NodeLink {
+    NodeLink {
+        output_node: output_node.to_owned(),
+        input_node: input_node.to_owned(),
+    }
+}
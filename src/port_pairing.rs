@@ -0,0 +1,189 @@
+use crate::pipewire_registry::Port;
+
+/// Pairs output and input ports by their audio channel. Only ports with the
+/// same DSP format are paired, so MIDI ports never end up on audio ports.
+/// Within a format a mono port is connected to every port of the other
+/// side, and ports without channel information are paired in order when
+/// both sides have the same count.
+pub fn pair_ports(output_ports: Vec<Port>, input_ports: Vec<Port>) -> Vec<(Port, Port)> {
+    let mut formats: Vec<&str> = Vec::new();
+    for port in output_ports.iter() {
+        if !formats.contains(&port.dsp_format.as_str()) {
+            formats.push(&port.dsp_format);
+        }
+    }
+
+    formats
+        .into_iter()
+        .flat_map(|format| {
+            let with_format = |ports: &[Port]| -> Vec<Port> {
+                ports
+                    .iter()
+                    .filter(|port| port.dsp_format == format)
+                    .cloned()
+                    .collect()
+            };
+            pair_format(with_format(&output_ports), with_format(&input_ports))
+        })
+        .collect()
+}
+
+fn pair_format(output_ports: Vec<Port>, input_ports: Vec<Port>) -> Vec<(Port, Port)> {
+    let is_mono =
+        |ports: &[Port]| !ports.is_empty() && ports.iter().all(|port| port.audio_channel == "MONO");
+
+    if is_mono(&output_ports) && !is_mono(&input_ports) {
+        let output_port = &output_ports[0];
+        return input_ports
+            .into_iter()
+            .map(|input_port| (output_port.clone(), input_port))
+            .collect();
+    }
+    if is_mono(&input_ports) && !is_mono(&output_ports) {
+        let input_port = &input_ports[0];
+        return output_ports
+            .into_iter()
+            .map(|output_port| (output_port, input_port.clone()))
+            .collect();
+    }
+
+    let pairs: Vec<(Port, Port)> = output_ports
+        .iter()
+        .filter(|port| !port.audio_channel.is_empty())
+        .flat_map(|output_port| {
+            input_ports
+                .iter()
+                .filter(|input_port| input_port.audio_channel == output_port.audio_channel)
+                .map(|input_port| (output_port.clone(), input_port.clone()))
+        })
+        .collect();
+    if pairs.is_empty() && output_ports.len() == input_ports.len() {
+        return output_ports.into_iter().zip(input_ports).collect();
+    }
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipewire_registry::PortDirection;
+
+    const AUDIO: &str = "32 bit float mono audio";
+    const MIDI: &str = "8 bit raw midi";
+
+    fn port(global_id: u32, direction: PortDirection, audio_channel: &str, format: &str) -> Port {
+        Port {
+            global_id,
+            id: global_id,
+            node_id: 0,
+            object_serial: u64::from(global_id),
+            name: format!("port_{global_id}"),
+            direction,
+            physical: false,
+            alias: String::new(),
+            group: String::new(),
+            path: String::new(),
+            dsp_format: String::from(format),
+            audio_channel: String::from(audio_channel),
+        }
+    }
+
+    fn output(global_id: u32, audio_channel: &str, format: &str) -> Port {
+        port(global_id, PortDirection::Out, audio_channel, format)
+    }
+
+    fn input(global_id: u32, audio_channel: &str, format: &str) -> Port {
+        port(global_id, PortDirection::In, audio_channel, format)
+    }
+
+    fn ids(pairs: Vec<(Port, Port)>) -> Vec<(u32, u32)> {
+        pairs
+            .into_iter()
+            .map(|(output, input)| (output.global_id, input.global_id))
+            .collect()
+    }
+
+    #[test]
+    fn pairs_ports_by_audio_channel() {
+        let pairs = pair_ports(
+            vec![output(1, "FL", AUDIO), output(2, "FR", AUDIO)],
+            vec![input(11, "FR", AUDIO), input(12, "FL", AUDIO)],
+        );
+        assert_eq!(ids(pairs), vec![(1, 12), (2, 11)]);
+    }
+
+    #[test]
+    fn falls_back_to_port_order_without_channels() {
+        let pairs = pair_ports(
+            vec![output(1, "", AUDIO), output(2, "", AUDIO)],
+            vec![input(11, "", AUDIO), input(12, "", AUDIO)],
+        );
+        assert_eq!(ids(pairs), vec![(1, 11), (2, 12)]);
+    }
+
+    #[test]
+    fn does_not_guess_with_mismatched_counts() {
+        let pairs = pair_ports(
+            vec![
+                output(1, "", AUDIO),
+                output(2, "", AUDIO),
+                output(3, "", AUDIO),
+            ],
+            vec![input(11, "", AUDIO), input(12, "", AUDIO)],
+        );
+        assert!(pairs.is_empty());
+    }
+
+    #[test]
+    fn fans_out_a_mono_output() {
+        let pairs = pair_ports(
+            vec![output(1, "MONO", AUDIO)],
+            vec![input(11, "FL", AUDIO), input(12, "FR", AUDIO)],
+        );
+        assert_eq!(ids(pairs), vec![(1, 11), (1, 12)]);
+    }
+
+    #[test]
+    fn folds_down_to_a_mono_input() {
+        let pairs = pair_ports(
+            vec![output(1, "FL", AUDIO), output(2, "FR", AUDIO)],
+            vec![input(11, "MONO", AUDIO)],
+        );
+        assert_eq!(ids(pairs), vec![(1, 11), (2, 11)]);
+    }
+
+    #[test]
+    fn does_not_fan_out_a_single_channel_port() {
+        let pairs = pair_ports(
+            vec![output(1, "FL", AUDIO)],
+            vec![input(11, "FL", AUDIO), input(12, "FR", AUDIO)],
+        );
+        assert_eq!(ids(pairs), vec![(1, 11)]);
+    }
+
+    #[test]
+    fn pairs_midi_ports_only_with_midi_ports() {
+        let pairs = pair_ports(
+            vec![
+                output(1, "FL", AUDIO),
+                output(2, "FR", AUDIO),
+                output(3, "", MIDI),
+            ],
+            vec![
+                input(11, "FL", AUDIO),
+                input(12, "FR", AUDIO),
+                input(13, "", MIDI),
+            ],
+        );
+        assert_eq!(ids(pairs), vec![(1, 11), (2, 12), (3, 13)]);
+    }
+
+    #[test]
+    fn leaves_midi_out_of_audio_fan_out() {
+        let pairs = pair_ports(
+            vec![output(1, "", MIDI)],
+            vec![input(11, "FL", AUDIO), input(12, "FR", AUDIO)],
+        );
+        assert!(pairs.is_empty());
+    }
+}
@@ -1,16 +1,84 @@
 use std::error::Error;
+use std::path::PathBuf;
+use std::process::ExitCode;
 use std::thread;
 
+use clap::Parser;
 use rlg::log::Log;
 
+use crate::config::{ListenAddress, ServerConfig};
+use crate::pipewire_registry::Patchbay;
+
+mod config;
+mod grpc_errors;
 mod grpc_services;
 mod grpc_services_loop;
+mod link_snapshot;
 mod pipewire_event_consumer;
 mod pipewire_factory;
 mod pipewire_loop;
 mod pipewire_registry;
+mod pmx_layout;
+mod port_pairing;
+
+#[derive(Parser)]
+#[command(version, about, long_about=None)]
+struct Arguments {
+    #[arg(short, long, env = "FR_PIPEWIRE_REGISTRY_LISTEN")]
+    listen: Option<String>,
+    #[arg(short, long, env = "FR_PIPEWIRE_REGISTRY_CONFIG")]
+    config: Option<PathBuf>,
+}
+
+fn main() -> ExitCode {
+    let arguments = Arguments::parse();
+    let config = match config::load_config(arguments.config.as_deref()) {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("{error:#}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let listen_address: ListenAddress = match arguments
+        .listen
+        .or(config.listen_address.clone())
+        .unwrap_or_else(|| String::from(config::DEFAULT_LISTEN_ADDRESS))
+        .parse()
+    {
+        Ok(listen_address) => listen_address,
+        Err(error) => {
+            eprintln!("{error:#}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let patchbay = match Patchbay::load(
+        config
+            .patchbay_file
+            .clone()
+            .or_else(config::patchbay_file_path),
+    ) {
+        Ok(patchbay) => patchbay,
+        Err(error) => {
+            eprintln!("{error:#}");
+            return ExitCode::FAILURE;
+        }
+    };
 
-fn main() -> Result<(), Box<dyn Error>> {
+    match run(config, listen_address, patchbay) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("{error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(
+    config: ServerConfig,
+    listen_address: ListenAddress,
+    mut patchbay: Patchbay,
+) -> Result<(), Box<dyn Error>> {
     fr_logging::setup_logging();
     let (logger_send, logger_receive) = tokio::sync::mpsc::unbounded_channel::<Log>();
     let logger_factory = fr_logging::LoggerFactory::new(logger_send);
@@ -18,6 +86,14 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let main_logger = logger_factory.new_logger(String::from("main_loop"));
 
+    let layout = pmx_layout::load_layout(&main_logger)
+        .map_err(|error| format!("Invalid pmx config: {error:#}"))?;
+    main_logger.log_info(&format!(
+        "Loaded {} node links from the pmx config",
+        layout.len()
+    ));
+    patchbay.set_layout(layout);
+
     let grpc_logger = logger_factory.new_logger(String::from("gRPC Service"));
 
     let (pipewire_registry_request_sender, pipewire_registry_request_receiver) =
@@ -25,14 +101,22 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let (pipewire_factory_request_sender, pipewire_factory_request_receiver) =
         pipewire::channel::channel();
+    let registry_factory_request_sender = pipewire_factory_request_sender.clone();
+
+    // The gRPC service and the PipeWire loop run until they fail, the first
+    // one that stops ends the daemon.
+    let (exit_sender, exit_receiver) = std::sync::mpsc::channel::<anyhow::Result<()>>();
 
     main_logger.log_info("Starting grpc services");
+    let grpc_exit_sender = exit_sender.clone();
     let _grpc_services_thread = thread::spawn(move || {
-        grpc_services_loop::run_grpc_service(
+        let result = grpc_services_loop::run_grpc_service(
             &grpc_logger,
+            listen_address,
             pipewire_registry_request_sender,
             pipewire_factory_request_sender,
-        )
+        );
+        let _ = grpc_exit_sender.send(result);
     });
 
     let (pipewire_event_sender, pipewire_event_receiver) = tokio::sync::mpsc::unbounded_channel();
@@ -46,19 +130,30 @@ fn main() -> Result<(), Box<dyn Error>> {
                 let mut pipewire_registry = pipewire_registry::PipewireRegistry::new(
                     pipewire_event_receiver,
                     pipewire_registry_request_receiver,
+                    registry_factory_request_sender,
+                    patchbay,
                 );
                 pipewire_registry.run().await;
             });
     });
 
     let pipewire_logger = logger_factory.new_logger(String::from("pipewire_loop"));
+    let consumer_logger = logger_factory.new_logger(String::from("pipewire_event_consumer"));
 
-    pipewire_loop::run_pipewire_loop(
-        &pipewire_logger,
-        pipewire_event_sender,
-        pipewire_factory_request_receiver,
-    )
-    .unwrap();
+    let _pipewire_thread = thread::spawn(move || {
+        let result = pipewire_loop::run_pipewire_loop(
+            &pipewire_logger,
+            consumer_logger,
+            &config,
+            pipewire_event_sender,
+            pipewire_factory_request_receiver,
+        );
+        let _ = exit_sender.send(result);
+    });
 
-    Ok(())
+    match exit_receiver.recv() {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(error)) => Err(format!("{error:#}").into()),
+        Err(_) => Err("The gRPC service and PipeWire loop stopped unexpectedly".into()),
+    }
 }